@@ -0,0 +1,10 @@
+-- ABIs uploaded for verified contracts, used to decode the calldata of `/tx/:hash`
+-- with named parameters before falling back to the `sig_names` signatures.
+CREATE TABLE IF NOT EXISTS contract_abis (
+    chain_id BIGINT NOT NULL,
+    -- checksummed, as in `transactions.to_address`
+    address VARCHAR NOT NULL,
+    -- JSON ABI as emitted by solc
+    abi JSONB NOT NULL,
+    PRIMARY KEY (chain_id, address)
+);
@@ -1,31 +1,42 @@
-use std::str::FromStr;
+use std::cmp::Reverse;
 
 use axum::{
-    extract::{Path, Query, State},
+    extract::State,
     middleware,
+    response::{IntoResponse, Response},
     routing::get,
     Json, Router,
 };
-use ethers_core::{types::Address, utils::to_checksum};
-use serde_json::{from_str, json, Number, Value};
+use deadpool_postgres::Client;
+use futures_util::TryStreamExt;
+use tokio::try_join;
+use tokio_postgres::types::ToSql;
 
+use super::export::{acquire_slot, cursor_stream, export_response, param_refs, SqlParams};
 use crate::{
     error::AppError,
-    middleware::{LongAlwaysCacheMiddleware, ShortAlwaysCacheMiddleware},
+    extract::{Path, Query},
+    middleware::{HeavyRateLimitMiddleware, LongAlwaysCacheMiddleware, ShortAlwaysCacheMiddleware},
     state::{AppState, STATE},
-    types::Pagination,
+    types::{
+        checksum_address, AddressFilter, AddressRole, AddressSummary, AddressSummaryResponse,
+        AddressTransaction, AddressTransactionsResponse, ChainActivity, Cursor, ExportFormat,
+        Pagination, ProxyDestination, ProxyDestinationListResponse, RoleCounts, TransactionSummary,
+        TRANSACTION_FROM,
+    },
 };
 
 pub fn routes() -> Router<()> {
     Router::new()
         .nest(
             "/",
-            Router::new().route("/:address", get(address)).route_layer(
-                middleware::from_fn_with_state(
+            Router::new()
+                .route("/:address", get(address))
+                .route("/:address/summary", get(address_summary))
+                .route_layer(middleware::from_fn_with_state(
                     STATE.clone(),
-                    ShortAlwaysCacheMiddleware::<true>::handler,
-                ),
-            ),
+                    ShortAlwaysCacheMiddleware::<(Pagination, AddressFilter)>::handler,
+                )),
         )
         .nest(
             "/proxy",
@@ -33,18 +44,34 @@ pub fn routes() -> Router<()> {
                 .route("/:address", get(proxy_address))
                 .route_layer(middleware::from_fn_with_state(
                     STATE.clone(),
-                    LongAlwaysCacheMiddleware::<false>::handler,
+                    LongAlwaysCacheMiddleware::<()>::handler,
                 )),
         )
+        .route_layer(middleware::from_fn_with_state(
+            STATE.clone(),
+            HeavyRateLimitMiddleware::handler,
+        ))
         .with_state(STATE.clone())
 }
 
+#[utoipa::path(
+    get,
+    path = "/address/proxy/{address}",
+    tag = "address",
+    params(("address" = String, Path, description = "Proxy contract address")),
+    responses(
+        (status = 200, description = "Logic contracts the proxy delegates to", body = ProxyDestinationListResponse),
+        (status = 400, description = "Invalid address", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
 pub async fn proxy_address(
     Path(address): Path<String>,
     State(state): State<AppState>,
-) -> Result<Json<Value>, AppError> {
+) -> Result<Json<ProxyDestinationListResponse>, AppError> {
     let postgres = state.postgres_pool.get().await?;
-    let address = to_checksum(&Address::from_str(&address)?, None);
+    let address = checksum_address(&address)?;
 
     let results = postgres
         .query(
@@ -55,67 +82,232 @@ pub async fn proxy_address(
 
     let data = results
         .iter()
-        .map(|result| {
-            Ok(json!({
-                "logic": result.try_get::<_, String>("logic")?,
-                "chain_id": result.try_get::<_, i64>("chain_id")?,
-            }))
-        })
-        .collect::<Result<Vec<_>, AppError>>()?;
+        .map(ProxyDestination::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
 
-    Ok(Json(json!({
-        "data": data,
-    })))
+    Ok(Json(data.into()))
 }
 
+#[utoipa::path(
+    get,
+    path = "/address/{address}",
+    tag = "address",
+    params(
+        ("address" = String, Path, description = "Address to list transactions of"),
+        Pagination,
+        AddressFilter,
+        crate::types::FormatQuery,
+    ),
+    responses(
+        (status = 200, description = "Transactions the address took part in. CSV and NDJSON stream every transaction past the cursor, newest first, ignoring `page` and `size`", content(
+            ("application/json" = AddressTransactionsResponse),
+            ("text/csv" = String),
+            ("application/x-ndjson" = String),
+        )),
+        (status = 400, description = "Invalid address, filter or pagination", body = ErrorResponse),
+        (status = 429, description = "Too many exports running", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
 pub async fn address(
     Path(address): Path<String>,
     Query(pagination): Query<Pagination>,
+    Query(filter): Query<AddressFilter>,
+    format: ExportFormat,
     State(state): State<AppState>,
-) -> Result<Json<Value>, AppError> {
+) -> Result<Response, AppError> {
+    pagination.validate()?;
+    filter.validate()?;
+    let address = checksum_address(&address)?;
+    // exports take their slot before holding a connection
+    let slot = match format {
+        ExportFormat::Json => None,
+        _ => Some(acquire_slot(None)?),
+    };
     let postgres = state.postgres_pool.get().await?;
-    let address = to_checksum(&Address::from_str(&address)?, None);
 
-    let results = postgres
-        .query(
-            "
-                WITH tb AS (
-                	SELECT * FROM transactions WHERE from_address = $1 OR to_address = $1 OR ARRAY[$1]::VARCHAR[] <@ ec_recover_addresses OR ARRAY[$1]::VARCHAR[] <@ closest_address
-                )
-                SELECT chain_id, from_address, to_address, closest_address, transaction_hash, transaction_index, value, error, function_signature, sig_names.name AS function_name, block_number, ec_pairing_count, ec_recover_addresses FROM tb LEFT JOIN sig_names ON tb.function_signature = sig_names.sig ORDER BY tb.id DESC OFFSET $2 LIMIT $3
-            ",
-            &[
-                &address,
-                &pagination.offset(),
-                &pagination.limit(),
-            ],
-        )
-        .await?;
+    let cursor = pagination.cursor()?;
+
+    let select = format!(
+        "
+        SELECT {} FROM {}
+        WHERE ({})
+        AND ($2::BIGINT IS NULL OR transactions.id < $2) AND ($3::BIGINT IS NULL OR transactions.id > $3)
+        AND ($4::BIGINT IS NULL OR transactions.chain_id = $4)
+        AND ($5::BIGINT IS NULL OR transactions.block_number >= $5) AND ($6::BIGINT IS NULL OR transactions.block_number <= $6)
+        AND ($7::BOOLEAN IS NULL OR (transactions.error IS NULL) = $7)
+        AND ($8::VARCHAR IS NULL OR transactions.function_signature = $8)
+        ",
+        TransactionSummary::COLUMNS,
+        TRANSACTION_FROM,
+        AddressRole::conditions(filter.role),
+    );
+    let mut params: SqlParams = vec![
+        Box::new(address.clone()),
+        Box::new(Cursor::before(cursor)),
+        Box::new(Cursor::after(cursor)),
+        Box::new(filter.chain_id),
+        Box::new(filter.from_block),
+        Box::new(filter.to_block),
+        Box::new(filter.is_success()),
+        Box::new(filter.function_signature.clone()),
+    ];
+
+    // exports hold every transaction past the cursor, newest first, whatever the page size
+    if format != ExportFormat::Json {
+        let name = address.clone();
+        let statement = format!("{} ORDER BY transactions.id DESC", select);
+        let transactions = cursor_stream::<TransactionSummary>(postgres, statement, params).map_ok(
+            move |transaction| {
+                let _slot = &slot;
+                AddressTransaction {
+                    roles: AddressRole::of(&address, &transaction),
+                    transaction,
+                }
+            },
+        );
+        return Ok(export_response(format, &name, transactions));
+    }
 
-    let datas = results
+    let statement = format!(
+        "{} ORDER BY transactions.id {} OFFSET $9 LIMIT $10",
+        select,
+        Cursor::order(cursor)
+    );
+    params.push(Box::new(pagination.offset()));
+    params.push(Box::new(pagination.limit()));
+    let results = postgres.query(&statement, &param_refs(&params)).await?;
+
+    let mut datas = results
         .iter()
-        .map(|result| {
-            Ok(json!({
-                "chain_id": result.try_get::<_, i64>("chain_id")?,
-                "from_address": result.try_get::<_, String>("from_address")?,
-                "to_address": result.try_get::<_, String>("to_address")?,
-                "closest_address": result.try_get::<_, Vec<String>>("closest_address")?,
-                "transaction_hash": result.try_get::<_, String>("transaction_hash")?,
-                "transaction_index": result.try_get::<_, i32>("transaction_index")?,
-                "value": from_str::<Number>(&result.try_get::<_, String>("value")?)?,
-                "error": result.try_get::<_, Option<String>>("error")?,
-                "function_signature": result.try_get::<_, String>("function_signature")?,
-                "function_name": result.try_get::<_, Option<String>>("function_name")?,
-                "block_number": result.try_get::<_, i64>("block_number")?,
-                "ec_pairing_count": result.try_get::<_, i16>("ec_pairing_count")?,
-                "ec_recover_addresses": result.try_get::<_, Vec<String>>("ec_recover_addresses")?,
-            }))
+        .map(TransactionSummary::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    datas.sort_by_key(|tx| Reverse(tx.id));
+    let cursors = pagination.cursors(&datas.iter().map(|tx| tx.id).collect::<Vec<_>>())?;
+    let datas = datas
+        .into_iter()
+        .map(|transaction| AddressTransaction {
+            roles: AddressRole::of(&address, &transaction),
+            transaction,
         })
-        .collect::<Result<Vec<_>, AppError>>()?;
+        .collect();
+
+    Ok(Json(AddressTransactionsResponse {
+        address,
+        pagination,
+        filter,
+        cursors,
+        data: datas,
+    })
+    .into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/address/{address}/summary",
+    tag = "address",
+    params(("address" = String, Path, description = "Address to summarize")),
+    responses(
+        (status = 200, description = "Aggregates over the address' transactions, its tags and proxy logic", body = AddressSummaryResponse),
+        (status = 400, description = "Invalid address", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn address_summary(
+    Path(address): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<AddressSummaryResponse>, AppError> {
+    let address = checksum_address(&address)?;
+    let postgres = state.postgres_pool.get().await?;
+
+    Ok(Json(summarize(&postgres, address).await?.into()))
+}
+
+/// Aggregates the summary of a checksummed address, shared with the search endpoint
+pub(crate) async fn summarize(
+    postgres: &Client,
+    address: String,
+) -> Result<AddressSummary, AppError> {
+    let conditions = AddressRole::conditions(None);
+    let totals_query = format!(
+                "
+                WITH txs AS (SELECT * FROM transactions WHERE {})
+                SELECT
+                    COUNT(*) AS transaction_count,
+                    COUNT(*) FILTER (WHERE from_address = $1) AS from_count,
+                    COUNT(*) FILTER (WHERE to_address = $1) AS to_count,
+                    COUNT(*) FILTER (WHERE ARRAY[$1]::VARCHAR[] <@ ec_recover_addresses) AS ecrecover_count,
+                    COUNT(*) FILTER (WHERE ARRAY[$1]::VARCHAR[] <@ closest_address) AS closest_count,
+                    COUNT(*) FILTER (WHERE error IS NOT NULL) AS failed_transaction_count,
+                    COALESCE(SUM(ec_pairing_count), 0)::BIGINT AS ec_pairing_count,
+                    COALESCE(SUM(gas_used_total), 0)::BIGINT AS gas_used_total,
+                    (SELECT COUNT(DISTINCT signer) FROM txs, UNNEST(ec_recover_addresses) AS signer) AS ec_recover_signer_count
+                FROM txs
+                ",
+        conditions
+    );
+    let chains_query = format!(
+                "
+                SELECT
+                    transactions.chain_id,
+                    COUNT(*) AS transaction_count,
+                    MIN(transactions.block_number) AS first_block,
+                    MAX(transactions.block_number) AS last_block,
+                    MIN(blocks.timestamp) AS first_timestamp,
+                    MAX(blocks.timestamp) AS last_timestamp
+                FROM transactions LEFT JOIN blocks ON blocks.chain_id = transactions.chain_id AND blocks.number = transactions.block_number
+                WHERE {}
+                GROUP BY transactions.chain_id
+                ORDER BY 2 DESC
+                ",
+        conditions
+    );
+
+    let params: &[&(dyn ToSql + Sync)] = &[&address];
+
+    let (totals, chains, tags, proxies) = try_join!(
+        postgres.query_one(&totals_query, params),
+        postgres.query(&chains_query, params),
+        postgres.query_one(
+            "SELECT COALESCE(ARRAY_AGG(DISTINCT tag), '{}') AS tags FROM tags WHERE address = $1",
+            params,
+        ),
+        postgres.query(
+            "SELECT logic, chainid AS chain_id FROM proxy_destination WHERE proxy = $1",
+            params,
+        ),
+    )?;
+
+    let chains = chains
+        .iter()
+        .map(ChainActivity::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
 
-    Ok(Json(json!({
-        "address": address,
-        "pagination": pagination,
-        "data": datas,
-    })))
+    Ok(AddressSummary {
+        transaction_count: totals.try_get("transaction_count")?,
+        role_counts: RoleCounts {
+            from: totals.try_get("from_count")?,
+            to: totals.try_get("to_count")?,
+            ecrecover: totals.try_get("ecrecover_count")?,
+            closest: totals.try_get("closest_count")?,
+        },
+        first_seen_timestamp: chains
+            .iter()
+            .filter_map(|chain| chain.first_timestamp)
+            .min(),
+        last_seen_timestamp: chains.iter().filter_map(|chain| chain.last_timestamp).max(),
+        chains,
+        failed_transaction_count: totals.try_get("failed_transaction_count")?,
+        ec_pairing_count: totals.try_get("ec_pairing_count")?,
+        ec_recover_signer_count: totals.try_get("ec_recover_signer_count")?,
+        gas_used_total: totals.try_get("gas_used_total")?,
+        tags: tags.try_get("tags")?,
+        proxy_destinations: proxies
+            .iter()
+            .map(ProxyDestination::try_from)
+            .collect::<Result<Vec<_>, _>>()?,
+        address,
+    })
 }
@@ -1,15 +1,24 @@
 use axum::{
-    extract::{Path, State},
+    extract::State,
     middleware,
+    response::{IntoResponse, Response},
     routing::get,
-    Json, Router,
+    Extension, Json, Router,
 };
-use serde_json::{from_str, json, Number, Value};
+use deadpool_postgres::Client;
 
+use super::export::{export_response, param_refs, row_stream, SqlParams};
 use crate::{
+    chains::CHAINS,
+    config::CONFIG,
     error::AppError,
-    middleware::LongAlwaysCacheMiddleware,
+    extract::Path,
+    middleware::{Immutable, LightRateLimitMiddleware, ShortAlwaysCacheMiddleware},
     state::{State as AppState, STATE},
+    types::{
+        Block, BlockResponse, ExportFormat, TransactionListResponse, TransactionSummary,
+        TRANSACTION_FROM,
+    },
 };
 
 pub fn routes() -> Router<()> {
@@ -18,83 +27,139 @@ pub fn routes() -> Router<()> {
         .route("/:chain-id/:block-number/txs", get(block_txs))
         .route_layer(middleware::from_fn_with_state(
             STATE.clone(),
-            LongAlwaysCacheMiddleware::<false>::handler,
+            ShortAlwaysCacheMiddleware::<()>::handler,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            STATE.clone(),
+            LightRateLimitMiddleware::handler,
         ))
         .with_state(STATE.clone())
 }
 
+#[utoipa::path(
+    get,
+    path = "/block/{chain-id}/{block-number}",
+    tag = "block",
+    params(
+        ("chain-id" = i64, Path, description = "Chain ID"),
+        ("block-number" = i64, Path, description = "Block number"),
+    ),
+    responses(
+        (status = 200, description = "Block found", body = BlockResponse),
+        (status = 400, description = "Invalid chain id or block number", body = ErrorResponse),
+        (status = 404, description = "Unknown chain or block not found", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
 pub async fn block(
     Path((chain_id, block_number)): Path<(String, String)>,
     State(state): State<AppState>,
-) -> Result<Json<Value>, AppError> {
+) -> Result<Response, AppError> {
+    let chain_id = chain_id
+        .parse::<i64>()
+        .map_err(AppError::invalid_chain_id)?;
+    CHAINS.ensure(chain_id)?;
+    let block_number = block_number
+        .parse::<i64>()
+        .map_err(AppError::invalid_parameter)?;
     let postgres = state.postgres_pool.get().await?;
-    let chain_id = chain_id.parse::<i64>()?;
-    let block_number = block_number.parse::<i64>()?;
 
     let results = postgres
         .query(
-            "SELECT number, timestamp, hash, parent_hash, transaction_count, nonce, miner, difficulty, total_difficulty, size, gas_limit, gas_used, base_fee_per_gas FROM blocks WHERE chain_id = $1 AND number = $2 LIMIT 1",
+            &format!(
+                "SELECT {} FROM blocks WHERE chain_id = $1 AND number = $2 LIMIT 1",
+                Block::COLUMNS
+            ),
             &[&chain_id, &block_number],
         )
         .await?;
-    let result = results.get(0).ok_or_else(AppError::not_found)?;
+    let result = results.first().ok_or_else(AppError::not_found)?;
+    let response = Json(BlockResponse::from(Block::try_from(result)?));
 
-    Ok(Json(json!({
-        "data": {
-            "chain_id": chain_id,
-            "number": result.try_get::<_, i64>("number")?,
-            "timestamp": result.try_get::<_, i64>("timestamp")?,
-            "hash": result.try_get::<_, String>("hash")?,
-            "parent_hash": result.try_get::<_, String>("parent_hash")?,
-            "transaction_count": result.try_get::<_, i32>("transaction_count")?,
-            "nonce": result.try_get::<_, String>("nonce")?,
-            "miner": result.try_get::<_, String>("miner")?,
-            "difficulty": result.try_get::<_, i64>("difficulty")?,
-            "total_difficulty": result.try_get::<_, f64>("total_difficulty")?,
-            "size": result.try_get::<_, i32>("size")?,
-            "gas_limit": result.try_get::<_, i64>("gas_limit")?,
-            "gas_used": result.try_get::<_, i64>("gas_used")?,
-            "base_fee_per_gas": result.try_get::<_, i64>("base_fee_per_gas")?,
-        },
-    })))
+    Ok(match is_final(&postgres, chain_id, block_number).await? {
+        true => (Extension(Immutable), response).into_response(),
+        false => response.into_response(),
+    })
 }
 
+#[utoipa::path(
+    get,
+    path = "/block/{chain-id}/{block-number}/txs",
+    tag = "block",
+    params(
+        ("chain-id" = i64, Path, description = "Chain ID"),
+        ("block-number" = i64, Path, description = "Block number"),
+        crate::types::FormatQuery,
+    ),
+    responses(
+        (status = 200, description = "Indexed transactions of the block. CSV and NDJSON hold one row per transaction", content(
+            ("application/json" = TransactionListResponse),
+            ("text/csv" = String),
+            ("application/x-ndjson" = String),
+        )),
+        (status = 400, description = "Invalid chain id or block number", body = ErrorResponse),
+        (status = 404, description = "Unknown chain", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
 pub async fn block_txs(
     Path((chain_id, block_number)): Path<(String, String)>,
+    format: ExportFormat,
     State(state): State<AppState>,
-) -> Result<Json<Value>, AppError> {
+) -> Result<Response, AppError> {
+    let chain_id = chain_id
+        .parse::<i64>()
+        .map_err(AppError::invalid_chain_id)?;
+    CHAINS.ensure(chain_id)?;
+    let block_number = block_number
+        .parse::<i64>()
+        .map_err(AppError::invalid_parameter)?;
     let postgres = state.postgres_pool.get().await?;
-    let chain_id = chain_id.parse::<i64>()?;
-    let block_number = block_number.parse::<i64>()?;
 
-    let results = postgres
-        .query(
-            "SELECT from_address, to_address, transaction_hash, transaction_index, value, error, function_signature, ec_pairing_count, ec_recover_addresses, sig_names.name AS function_name, gas_used_total, gas_used_first_degree FROM transactions LEFT JOIN sig_names ON transactions.function_signature = sig_names.sig WHERE chain_id = $1 AND block_number = $2",
-            &[&chain_id, &block_number],
-        )
-        .await?;
+    let statement = format!(
+        "SELECT {} FROM {} WHERE transactions.chain_id = $1 AND transactions.block_number = $2",
+        TransactionSummary::COLUMNS,
+        TRANSACTION_FROM,
+    );
+    let params: SqlParams = vec![Box::new(chain_id), Box::new(block_number)];
+
+    if format != ExportFormat::Json {
+        return Ok(export_response(
+            format,
+            &format!("block-{}-{}", chain_id, block_number),
+            row_stream::<TransactionSummary>(postgres, statement, params),
+        ));
+    }
+
+    let results = postgres.query(&statement, &param_refs(&params)).await?;
     let datas = results
         .iter()
-        .map(|result| {
-            Ok(json!({
-                "chain_id": chain_id,
-                "from_address": result.try_get::<_, String>("from_address")?,
-                "to_address": result.try_get::<_, String>("to_address")?,
-                "transaction_hash": result.try_get::<_, String>("transaction_hash")?,
-                "transaction_index": result.try_get::<_, i32>("transaction_index")?,
-                "value": from_str::<Number>(&result.try_get::<_, String>("value")?)?,
-                "error": result.try_get::<_, Option<String>>("error")?,
-                "function_signature": result.try_get::<_, String>("function_signature")?,
-                "function_name": result.try_get::<_, Option<String>>("function_name")?,
-                "ec_pairing_count": result.try_get::<_, i16>("ec_pairing_count")?,
-                "ec_recover_addresses": result.try_get::<_, Vec<String>>("ec_recover_addresses")?,
-                "gas_used_total": result.try_get::<_, i64>("gas_used_total")?,
-                "gas_used_first_degree": result.try_get::<_, i64>("gas_used_first_degree")?,
-            }))
-        })
-        .collect::<Result<Vec<_>, AppError>>()?;
+        .map(TransactionSummary::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    let response = Json(TransactionListResponse::from(datas));
 
-    Ok(Json(json!({
-        "data": datas
-    })))
+    Ok(match is_final(&postgres, chain_id, block_number).await? {
+        true => (Extension(Immutable), response).into_response(),
+        false => response.into_response(),
+    })
+}
+
+/// Whether the block is indexed and [`finality_depth`](crate::config::Config::finality_depth)
+/// blocks below the indexed head of its chain. The indexer moving on in order, such a block has
+/// all its transactions indexed and is no longer reorganized.
+async fn is_final(postgres: &Client, chain_id: i64, block_number: i64) -> Result<bool, AppError> {
+    let row = postgres
+        .query_one(
+            "
+            SELECT EXISTS (SELECT 1 FROM blocks WHERE chain_id = $1 AND number = $2)
+            AND $2 <= (SELECT MAX(number) FROM blocks WHERE chain_id = $1) - $3 AS is_final
+            ",
+            &[&chain_id, &block_number, &CONFIG.finality_depth],
+        )
+        .await?;
+    Ok(row
+        .try_get::<_, Option<bool>>("is_final")?
+        .unwrap_or_default())
 }
@@ -0,0 +1,51 @@
+use axum::{middleware, routing::get, Json, Router};
+
+use crate::{
+    chains::CHAINS,
+    error::AppError,
+    extract::Path,
+    middleware::LightRateLimitMiddleware,
+    state::STATE,
+    types::{ChainListResponse, ChainResponse},
+};
+
+pub fn routes() -> Router<()> {
+    Router::new()
+        .route("/", get(chains))
+        .route("/:chain-id", get(chain))
+        .route_layer(middleware::from_fn_with_state(
+            STATE.clone(),
+            LightRateLimitMiddleware::handler,
+        ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/chains",
+    tag = "chain",
+    responses(
+        (status = 200, description = "Every chain in the registry", body = ChainListResponse),
+    )
+)]
+pub async fn chains() -> Json<ChainListResponse> {
+    Json(CHAINS.all().cloned().collect::<Vec<_>>().into())
+}
+
+#[utoipa::path(
+    get,
+    path = "/chains/{chain-id}",
+    tag = "chain",
+    params(("chain-id" = i64, Path, description = "Chain ID")),
+    responses(
+        (status = 200, description = "Chain found", body = ChainResponse),
+        (status = 400, description = "Invalid chain id", body = ErrorResponse),
+        (status = 404, description = "Chain not in the registry", body = ErrorResponse),
+    )
+)]
+pub async fn chain(Path(chain_id): Path<String>) -> Result<Json<ChainResponse>, AppError> {
+    let chain_id = chain_id
+        .parse::<i64>()
+        .map_err(AppError::invalid_chain_id)?;
+
+    Ok(Json(CHAINS.ensure(chain_id)?.clone().into()))
+}
@@ -0,0 +1,286 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use anyhow::{anyhow, Error};
+use async_stream::try_stream;
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::header::{ACCEPT, CONTENT_DISPOSITION, CONTENT_TYPE, VARY},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Router,
+};
+use csv::Writer;
+use deadpool_postgres::Client;
+use futures_util::{pin_mut, stream, Stream, StreamExt};
+use log::error;
+use once_cell::sync::Lazy;
+use serde_json::to_vec;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_postgres::{types::ToSql, Row};
+
+use crate::{
+    chains::CHAINS,
+    config::CONFIG,
+    error::AppError,
+    extract::Query,
+    middleware::{ApiKey, ExportRateLimitMiddleware, Uncacheable},
+    state::{AppState, STATE},
+    types::{
+        checksum_address, AddressRole, ExportFormat, ExportRecord, TransactionExportFilter,
+        TransactionSummary, TRANSACTION_FROM,
+    },
+};
+
+/// Rows fetched from the server-side cursor per round trip
+const EXPORT_BATCH_SIZE: i32 = 1000;
+
+/// Running exports per API key, bounded by `EXPORT_CONCURRENCY`
+static EXPORT_SLOTS: Lazy<Mutex<HashMap<String, Arc<Semaphore>>>> = Lazy::new(Default::default);
+
+/// Running exports of every key and address, bounded by `EXPORT_TOTAL_CONCURRENCY` so that they
+/// cannot hold every pooled Postgres connection
+static EXPORTS: Lazy<Arc<Semaphore>> =
+    Lazy::new(|| Arc::new(Semaphore::new(CONFIG.export_total_concurrency)));
+
+pub fn routes() -> Router<()> {
+    Router::new()
+        .route("/transactions", get(transactions))
+        .route_layer(middleware::from_fn_with_state(
+            STATE.clone(),
+            ExportRateLimitMiddleware::handler,
+        ))
+        .with_state(STATE.clone())
+}
+
+/// Streams every transaction matching the filter, without the pagination limits of the public endpoints
+///
+/// Requires an API key, sent as `X-API-Key` or `?api_key=`. Each key may run a limited number
+/// of exports at once. Transactions are ordered by id and written as NDJSON unless CSV is asked for.
+#[utoipa::path(
+    get,
+    path = "/export/transactions",
+    tag = "export",
+    params(TransactionExportFilter, crate::types::FormatQuery),
+    security(("api_key" = [])),
+    responses(
+        (status = 200, description = "Matching transactions, one per line", content(
+            ("application/x-ndjson" = String),
+            ("text/csv" = String),
+        )),
+        (status = 400, description = "Invalid filter", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 429, description = "Too many concurrent exports for this API key or in total", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn transactions(
+    api_key: ApiKey,
+    Query(filter): Query<TransactionExportFilter>,
+    format: ExportFormat,
+    State(state): State<AppState>,
+) -> Result<Response, AppError> {
+    filter.validate()?;
+    if let Some(chain_id) = filter.chain_id {
+        CHAINS.ensure(chain_id)?;
+    }
+    let address = filter
+        .address
+        .as_deref()
+        .map(checksum_address)
+        .transpose()?;
+    let format = match format {
+        ExportFormat::Json => ExportFormat::Ndjson,
+        format => format,
+    };
+
+    let slot = acquire_slot(Some(&api_key.key))?;
+    let postgres = state.postgres_pool.get().await?;
+
+    let statement = format!(
+        "
+        SELECT {} FROM {}
+        WHERE ($1::VARCHAR IS NULL OR ({}))
+        AND ($2::BIGINT IS NULL OR transactions.chain_id = $2)
+        AND ($3::BIGINT IS NULL OR transactions.block_number >= $3) AND ($4::BIGINT IS NULL OR transactions.block_number <= $4)
+        AND ($5::VARCHAR IS NULL OR EXISTS (
+            SELECT 1 FROM tags WHERE tags.tag = $5 AND (
+                tags.address = transactions.from_address OR tags.address = transactions.to_address
+                OR tags.address = ANY(transactions.ec_recover_addresses) OR tags.address = ANY(transactions.closest_address)
+            )
+        ))
+        ORDER BY transactions.id
+        ",
+        TransactionSummary::COLUMNS,
+        TRANSACTION_FROM,
+        AddressRole::conditions(None),
+    );
+    let params: SqlParams = vec![
+        Box::new(address),
+        Box::new(filter.chain_id),
+        Box::new(filter.from_block),
+        Box::new(filter.to_block),
+        Box::new(filter.tag),
+    ];
+
+    // the slot is released once the body is dropped, whether the download finished or not
+    let transactions =
+        cursor_stream::<TransactionSummary>(postgres, statement, params).map(move |item| {
+            let _slot = &slot;
+            item
+        });
+    Ok(export_response(format, "transactions", transactions))
+}
+
+/// Permits of a running export, released once dropped
+pub struct ExportSlot {
+    _key: Option<OwnedSemaphorePermit>,
+    _total: OwnedSemaphorePermit,
+}
+
+/// Slot among all running exports, and among those of `key` when given
+pub fn acquire_slot(key: Option<&str>) -> Result<ExportSlot, AppError> {
+    let key = key
+        .map(|key| {
+            let semaphore = EXPORT_SLOTS
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .entry(key.to_string())
+                .or_insert_with(|| Arc::new(Semaphore::new(CONFIG.export_concurrency)))
+                .clone();
+            semaphore.try_acquire_owned().map_err(|_| {
+                AppError::too_many_requests(anyhow!(
+                    "At most {} concurrent exports per API key",
+                    CONFIG.export_concurrency
+                ))
+            })
+        })
+        .transpose()?;
+    let total = EXPORTS.clone().try_acquire_owned().map_err(|_| {
+        AppError::too_many_requests(anyhow!("Too many exports are running, try again later"))
+    })?;
+    Ok(ExportSlot {
+        _key: key,
+        _total: total,
+    })
+}
+
+/// Owned query parameters, so that a query can outlive the handler building it
+pub type SqlParams = Vec<Box<dyn ToSql + Sync + Send>>;
+
+pub fn param_refs(params: &SqlParams) -> Vec<&(dyn ToSql + Sync)> {
+    params
+        .iter()
+        .map(|param| param.as_ref() as &(dyn ToSql + Sync))
+        .collect()
+}
+
+/// Rows of `statement` converted one by one as Postgres sends them
+pub fn row_stream<T>(
+    postgres: Client,
+    statement: String,
+    params: SqlParams,
+) -> impl Stream<Item = Result<T, Error>>
+where
+    T: for<'a> TryFrom<&'a Row, Error = Error>,
+{
+    try_stream! {
+        let rows = postgres.query_raw(&statement, param_refs(&params)).await?;
+        pin_mut!(rows);
+        while let Some(row) = rows.next().await {
+            yield T::try_from(&row?)?;
+        }
+    }
+}
+
+/// Rows of `statement` read through a server-side cursor, in batches of [`EXPORT_BATCH_SIZE`],
+/// so that a result of any size never sits in memory at once
+pub fn cursor_stream<T>(
+    mut postgres: Client,
+    statement: String,
+    params: SqlParams,
+) -> impl Stream<Item = Result<T, Error>>
+where
+    T: for<'a> TryFrom<&'a Row, Error = Error>,
+{
+    try_stream! {
+        let transaction = postgres.build_transaction().read_only(true).start().await?;
+        let portal = transaction.bind(statement.as_str(), &param_refs(&params)).await?;
+        loop {
+            let rows = transaction.query_portal(&portal, EXPORT_BATCH_SIZE).await?;
+            if rows.is_empty() {
+                break;
+            }
+            for row in rows {
+                yield T::try_from(&row)?;
+            }
+        }
+    }
+}
+
+/// Streams `items` as CSV, with a header row, or as NDJSON, downloaded as `name`
+pub fn export_response<T, S>(format: ExportFormat, name: &str, items: S) -> Response
+where
+    T: ExportRecord,
+    S: Stream<Item = Result<T, Error>> + Send + 'static,
+{
+    let header = match format {
+        ExportFormat::Csv => csv_line(T::HEADERS),
+        ExportFormat::Json | ExportFormat::Ndjson => Ok(Bytes::new()),
+    };
+    let lines = items.map(move |item| {
+        let item = item?;
+        match format {
+            ExportFormat::Csv => csv_line(item.record()?),
+            ExportFormat::Json | ExportFormat::Ndjson => {
+                let mut line = to_vec(&item)?;
+                line.push(b'\n');
+                Ok(Bytes::from(line))
+            }
+        }
+    });
+    let body = stream::once(async { header }).chain(lines).inspect(|line| {
+        if let Err(e) = line {
+            error!("Failed to stream export: {:?}", e);
+        }
+    });
+
+    // keep the filename a valid, unquoted header value whatever the path held
+    let name = name
+        .chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                true => c,
+                false => '_',
+            },
+        )
+        .collect::<String>();
+    (
+        Extension(Uncacheable),
+        [
+            (CONTENT_TYPE, format.content_type().to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.{}\"", name, format.extension()),
+            ),
+            (VARY, ACCEPT.to_string()),
+        ],
+        Body::from_stream(body),
+    )
+        .into_response()
+}
+
+fn csv_line<I, F>(record: I) -> Result<Bytes, Error>
+where
+    I: IntoIterator<Item = F>,
+    F: AsRef<[u8]>,
+{
+    let mut writer = Writer::from_writer(Vec::new());
+    writer.write_record(record)?;
+    Ok(Bytes::from(writer.into_inner()?))
+}
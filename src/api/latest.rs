@@ -1,59 +1,285 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::HashSet,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-use anyhow::Error;
+use anyhow::{anyhow, Error};
 use async_stream::try_stream;
 use axum::{
     extract::State,
+    http::HeaderMap,
+    middleware,
     response::{
         sse::{Event, KeepAlive},
         Sse,
     },
     routing::get,
-    Router,
+    Json, Router,
+};
+use backon::{BackoffBuilder, ExponentialBackoff, ExponentialBuilder, Retryable};
+use futures_util::{
+    stream::{poll_fn, BoxStream},
+    Stream, StreamExt,
 };
-use futures_util::{Stream, StreamExt};
-use log::{error, info};
-use serde_json::{from_str, json, Number, Value};
-use tokio::{sync::watch, time::interval, try_join};
-use tokio_stream::wrappers::IntervalStream;
+use log::{error, info, warn};
+use serde::Serialize;
+use serde_json::{to_value, Value};
+use tokio::{
+    select,
+    sync::{broadcast, broadcast::error::RecvError, watch, Notify},
+    task::JoinHandle,
+    time::sleep,
+    try_join,
+};
+use tokio_postgres::{AsyncMessage, NoTls};
 use tower_http::cors::{Any, CorsLayer};
 
-use crate::{config::CONFIG, state::STATE};
+use crate::{
+    chains::CHAINS,
+    config::CONFIG,
+    error::AppError,
+    extract::Query,
+    middleware::LightRateLimitMiddleware,
+    state::STATE,
+    types::{
+        checksum_address, BlockListResponse, BlockSummary, DataResponse, DeliveredIds, FeedItem,
+        FeedMode, FeedQuery, LatestQuery, LiveFilter, TransactionListResponse, TransactionSummary,
+        TxFeedFilter, TRAILING_IDS, TRANSACTION_FROM,
+    },
+};
+
+/// Most transactions fetched as a delta per poll, the rest follow on the next polls
+const MAX_TX_DELTA: i64 = 1000;
+
+/// Interval between polls while listening, only catching notifications that were missed
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Longest wait before retrying a failed update or reconnecting the listener
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
 
+/// Most blocks replayed to a client resuming a block feed
+const MAX_BLOCK_REPLAY: i64 = 100;
+
+/// Snapshots of the latest blocks and transactions, plus the new items found by every poll
 pub struct LatestState {
-    latest_blocks_rx: watch::Receiver<Value>,
-    latest_txs_rx: watch::Receiver<Value>,
+    latest_blocks_rx: watch::Receiver<Arc<Vec<BlockSummary>>>,
+    latest_txs_rx: watch::Receiver<Arc<Vec<TransactionSummary>>>,
+    new_blocks_tx: broadcast::Sender<Arc<Vec<BlockSummary>>>,
+    new_txs_tx: broadcast::Sender<Arc<Vec<TransactionSummary>>>,
 }
 
-pub fn routes() -> Router<()> {
-    let (latest_blocks_tx, latest_blocks_rx) = watch::channel(json!(null));
-    let (latest_txs_tx, latest_txs_rx) = watch::channel(json!(null));
+impl LatestState {
+    /// Starts the updater shared by the SSE and websocket feeds. It refreshes on every
+    /// notification on `latest.notify_channel`, and polls when none is configured or the
+    /// listener is disconnected.
+    pub fn spawn() -> Arc<Self> {
+        let (latest_blocks_tx, latest_blocks_rx) = watch::channel(Arc::new(Vec::new()));
+        let (latest_txs_tx, latest_txs_rx) = watch::channel(Arc::new(Vec::new()));
+        let (new_blocks_tx, _) = broadcast::channel(64);
+        let (new_txs_tx, _) = broadcast::channel(64);
 
-    let state = Arc::new(LatestState {
-        latest_blocks_rx,
-        latest_txs_rx,
-    });
+        let state = Arc::new(LatestState {
+            latest_blocks_rx,
+            latest_txs_rx,
+            new_blocks_tx: new_blocks_tx.clone(),
+            new_txs_tx: new_txs_tx.clone(),
+        });
+
+        let wake = Arc::new(Notify::new());
+        // First update right away
+        wake.notify_one();
+        let listening = Arc::new(AtomicBool::new(false));
+        if let Some(channel) = CONFIG.latest.notify_channel.clone() {
+            tokio::spawn(listen(channel, wake.clone(), listening.clone()));
+        }
+
+        let mut updater = Updater {
+            latest_blocks_tx,
+            latest_txs_tx,
+            new_blocks_tx,
+            new_txs_tx,
+            delivered_txs: None,
+            seen_blocks: None,
+        };
+        tokio::spawn(async move {
+            let mut backoff: Option<ExponentialBackoff> = None;
+            loop {
+                let period = match listening.load(Ordering::Relaxed) {
+                    true => IDLE_POLL_INTERVAL,
+                    false => Duration::from_secs(CONFIG.latest.poll_interval),
+                };
+                select! {
+                    _ = wake.notified() => {}
+                    _ = sleep(period) => {}
+                }
+
+                match updater.update().await {
+                    Ok(()) => backoff = None,
+                    Err(e) => {
+                        let delay = backoff
+                            .get_or_insert_with(|| retry_backoff().build())
+                            .next()
+                            .unwrap_or(MAX_RETRY_DELAY);
+                        error!(
+                            "Failed to update latest blocks and txs, retrying in {:?}: {}",
+                            delay, e
+                        );
+                        sleep(delay).await;
+                    }
+                }
+            }
+        });
+
+        state
+    }
+
+    /// Blocks that entered the latest blocks since the previous poll
+    pub fn subscribe_blocks(&self) -> broadcast::Receiver<Arc<Vec<BlockSummary>>> {
+        self.new_blocks_tx.subscribe()
+    }
+
+    /// Transactions indexed since the previous poll, in insertion order
+    pub fn subscribe_txs(&self) -> broadcast::Receiver<Arc<Vec<TransactionSummary>>> {
+        self.new_txs_tx.subscribe()
+    }
+}
+
+/// Publishes the snapshots and the deltas found by every poll
+struct Updater {
+    latest_blocks_tx: watch::Sender<Arc<Vec<BlockSummary>>>,
+    latest_txs_tx: watch::Sender<Arc<Vec<TransactionSummary>>>,
+    new_blocks_tx: broadcast::Sender<Arc<Vec<BlockSummary>>>,
+    new_txs_tx: broadcast::Sender<Arc<Vec<TransactionSummary>>>,
+    delivered_txs: Option<DeliveredIds>,
+    seen_blocks: Option<HashSet<(i64, i64)>>,
+}
+
+impl Updater {
+    async fn update(&mut self) -> Result<(), Error> {
+        let delivered_txs = match &mut self.delivered_txs {
+            Some(delivered) => delivered,
+            None => self
+                .delivered_txs
+                .insert(DeliveredIds::new(Some(get_last_tx_id().await?))),
+        };
+        let (latest_txs, latest_blocks, new_txs) = try_join!(
+            get_latest_txs(),
+            get_latest_block(),
+            get_txs_since(delivered_txs.rescan_from().unwrap_or_default())
+        )?;
 
-    tokio::spawn(async move {
-        if let Err(e) = async {
-            let mut interval = IntervalStream::new(interval(Duration::from_secs(3)));
-            while let Some(_) = interval.next().await {
-                let (latest_txs, latest_block) = try_join!(get_latest_txs(), get_latest_block())?;
-                latest_txs_tx.send_replace(latest_txs);
-                latest_blocks_tx.send_replace(latest_block);
+        let blocks = latest_blocks
+            .iter()
+            .map(|block| (block.chain_id, block.number))
+            .collect::<HashSet<_>>();
+        if let Some(seen_blocks) = &self.seen_blocks {
+            let new_blocks = latest_blocks
+                .iter()
+                .filter(|block| !seen_blocks.contains(&(block.chain_id, block.number)))
+                .cloned()
+                .collect::<Vec<_>>();
+            if !new_blocks.is_empty() {
+                // Only fails when nobody is subscribed
+                let _ = self.new_blocks_tx.send(Arc::new(new_blocks));
             }
-            Ok::<(), Error>(())
         }
-        .await
-        {
-            error!("Failed to update latest blocks and txs: {}", e);
-            panic!("{:?}", e);
+        self.seen_blocks = Some(blocks);
+
+        let new_txs = delivered_txs.retain_new(new_txs);
+        if !new_txs.is_empty() {
+            let _ = self.new_txs_tx.send(Arc::new(new_txs));
+        }
+
+        self.latest_txs_tx.send_replace(Arc::new(latest_txs));
+        self.latest_blocks_tx.send_replace(Arc::new(latest_blocks));
+        Ok(())
+    }
+}
+
+fn retry_backoff() -> ExponentialBuilder {
+    ExponentialBuilder::default()
+        .with_max_delay(MAX_RETRY_DELAY)
+        .with_max_times(usize::MAX)
+}
+
+/// Wakes the updater on every notification the indexer sends on `channel`, reconnecting
+/// with backoff. `listening` is cleared while the connection is down so that the updater
+/// falls back to polling.
+async fn listen(channel: String, wake: Arc<Notify>, listening: Arc<AtomicBool>) {
+    loop {
+        let connection = (|| connect_listener(&channel, wake.clone()))
+            .retry(&retry_backoff())
+            .notify(|e, delay| {
+                warn!(
+                    "Failed to listen on {}, retrying in {:?}: {}",
+                    channel, delay, e
+                )
+            })
+            .await;
+        let Ok((_client, connection)) = connection else {
+            continue;
+        };
+
+        info!("Listening for notifications on {}", channel);
+        listening.store(true, Ordering::Relaxed);
+        match connection.await {
+            Ok(Ok(())) => warn!("Notification connection closed"),
+            Ok(Err(e)) => warn!("Notification connection failed: {}", e),
+            Err(e) => warn!("Notification connection task failed: {}", e),
+        }
+        listening.store(false, Ordering::Relaxed);
+    }
+}
+
+type ListenerTask = JoinHandle<Result<(), tokio_postgres::Error>>;
+
+/// Opens a dedicated connection running `LISTEN channel`. The returned client must be kept
+/// alive for as long as the connection task runs.
+async fn connect_listener(
+    channel: &str,
+    wake: Arc<Notify>,
+) -> Result<(tokio_postgres::Client, ListenerTask), Error> {
+    let (client, mut connection) = CONFIG
+        .postgres_config()
+        .get_pg_config()?
+        .connect(NoTls)
+        .await?;
+
+    let notify = wake.clone();
+    let connection = tokio::spawn(async move {
+        let mut messages = poll_fn(move |cx| connection.poll_message(cx));
+        while let Some(message) = messages.next().await {
+            if let AsyncMessage::Notification(_) = message? {
+                notify.notify_one();
+            }
         }
+        Ok(())
     });
 
+    client
+        .batch_execute(&format!("LISTEN \"{}\"", channel.replace('"', "\"\"")))
+        .await?;
+    // Catch up on anything indexed while the connection was down
+    wake.notify_one();
+
+    Ok((client, connection))
+}
+
+pub fn routes(state: Arc<LatestState>) -> Router<()> {
     let mut router = Router::new()
+        .route("/blocks", get(latest_blocks))
+        .route("/txs", get(latest_txs))
         .route("/blocks/sse", get(latest_block_sse))
         .route("/txs/sse", get(latest_txs_sse))
+        .route_layer(middleware::from_fn_with_state(
+            STATE.clone(),
+            LightRateLimitMiddleware::handler,
+        ))
         .with_state(state);
 
     if CONFIG.is_dev {
@@ -64,7 +290,99 @@ pub fn routes() -> Router<()> {
     router
 }
 
-pub async fn get_latest_block() -> Result<Value, Error> {
+/// Validates the filters of a live feed, expanding `tag` to the addresses holding it
+pub async fn resolve_filter(
+    chain_id: Option<i64>,
+    address: Option<&str>,
+    tag: Option<&str>,
+) -> Result<LiveFilter, AppError> {
+    if let Some(chain_id) = chain_id {
+        CHAINS.ensure(chain_id)?;
+    }
+
+    let mut addresses: Option<HashSet<String>> = None;
+    if let Some(address) = address {
+        let address = checksum_address(address)?;
+        addresses = Some(HashSet::from([address]));
+    }
+    if let Some(tag) = tag {
+        let postgres = STATE.postgres_pool.get().await?;
+        let results = postgres
+            .query("SELECT DISTINCT address FROM tags WHERE tag = $1", &[&tag])
+            .await?;
+        if results.is_empty() {
+            return Err(AppError::invalid_parameter(anyhow!("Unknown tag {}", tag)));
+        }
+        let tagged = results
+            .iter()
+            .map(|row| row.try_get("address"))
+            .collect::<Result<HashSet<String>, _>>()?;
+        addresses = Some(match addresses {
+            Some(addresses) => addresses.intersection(&tagged).cloned().collect(),
+            None => tagged,
+        });
+    }
+
+    Ok(LiveFilter {
+        chain_id,
+        addresses,
+    })
+}
+
+async fn get_last_tx_id() -> Result<i64, Error> {
+    let postgres = STATE.postgres_pool.get().await?;
+    let result = postgres
+        .query_one(
+            "SELECT COALESCE(MAX(id), 0)::BIGINT AS id FROM transactions",
+            &[],
+        )
+        .await?;
+    Ok(result.try_get("id")?)
+}
+
+/// Transactions inserted after `id`, oldest first. The [`TRAILING_IDS`] rescanned by the
+/// callers come on top of the [`MAX_TX_DELTA`] new ones.
+pub async fn get_txs_since(id: i64) -> Result<Vec<TransactionSummary>, Error> {
+    let postgres = STATE.postgres_pool.get().await?;
+    let results = postgres
+        .query(
+            &format!(
+                "SELECT {} FROM {} WHERE transactions.id > $1 ORDER BY transactions.id ASC LIMIT $2",
+                TransactionSummary::COLUMNS,
+                TRANSACTION_FROM,
+            ),
+            &[&id, &(MAX_TX_DELTA + TRAILING_IDS)],
+        )
+        .await?;
+    results
+        .iter()
+        .map(TransactionSummary::try_from)
+        .collect::<Result<Vec<_>, _>>()
+}
+
+/// Blocks holding indexed transactions with an id above `id`, oldest first. As for
+/// transactions, the [`TRAILING_IDS`] rescanned come on top of [`MAX_BLOCK_REPLAY`].
+pub async fn get_blocks_since(id: i64) -> Result<Vec<BlockSummary>, Error> {
+    let postgres = STATE.postgres_pool.get().await?;
+    let results = postgres
+        .query(
+            "
+            SELECT blocks.id::BIGINT AS id, blocks.chain_id, blocks.number, blocks.timestamp, blocks.hash, blocks.transaction_count, COUNT(*) AS related_transaction_count, blocks.gas_limit, blocks.gas_used
+            FROM blocks JOIN transactions ON blocks.chain_id = transactions.chain_id AND blocks.number = transactions.block_number
+            WHERE blocks.id > $1
+            GROUP BY blocks.id, blocks.chain_id, blocks.number, blocks.timestamp, blocks.hash, blocks.transaction_count, blocks.gas_limit, blocks.gas_used
+            ORDER BY blocks.id ASC LIMIT $2
+            ",
+            &[&id, &(MAX_BLOCK_REPLAY + TRAILING_IDS)],
+        )
+        .await?;
+    results
+        .iter()
+        .map(BlockSummary::try_from)
+        .collect::<Result<Vec<_>, _>>()
+}
+
+pub async fn get_latest_block() -> Result<Vec<BlockSummary>, Error> {
     let postgres = STATE.postgres_pool.get().await?;
     let results = postgres
         .query(
@@ -75,92 +393,359 @@ pub async fn get_latest_block() -> Result<Value, Error> {
             txs AS (
             	SELECT *, COUNT(*) AS rtc FROM latest_txs GROUP BY chain_id, block_number
             )
-            SELECT blocks.chain_id, number, timestamp, hash, transaction_count, txs.rtc AS related_transaction_count, gas_limit, gas_used FROM blocks LEFT JOIN txs ON blocks.chain_id = txs.chain_id AND blocks.number = txs.block_number WHERE txs.rtc > 0 ORDER BY timestamp DESC, id DESC LIMIT 20
+            SELECT blocks.id::BIGINT AS id, blocks.chain_id, number, timestamp, hash, transaction_count, txs.rtc AS related_transaction_count, gas_limit, gas_used FROM blocks LEFT JOIN txs ON blocks.chain_id = txs.chain_id AND blocks.number = txs.block_number WHERE txs.rtc > 0 ORDER BY timestamp DESC, id DESC LIMIT $1
             ",
-            &[],
+            &[&CONFIG.latest.blocks_size],
         )
         .await?;
-    let datas = results
+    results
         .iter()
-        .map(|row| {
-            Ok(json!({
-                "chain_id": row.try_get::<_, i64>("chain_id")?,
-                "number": row.try_get::<_, i64>("number")?,
-                "timestamp": row.try_get::<_, i64>("timestamp")?,
-                "hash": row.try_get::<_, String>("hash")?,
-                "transaction_count": row.try_get::<_, i32>("transaction_count")?,
-                "related_transaction_count": row.try_get::<_, i64>("related_transaction_count")?,
-                "gas_limit": row.try_get::<_, i64>("gas_limit")?,
-                "gas_used": row.try_get::<_, i64>("gas_used")?,
-            }))
-        })
-        .collect::<Result<Vec<_>, Error>>()?;
-
-    Ok(json!({
-        "data": datas,
-    }))
+        .map(BlockSummary::try_from)
+        .collect::<Result<Vec<_>, _>>()
 }
 
-pub async fn get_latest_txs() -> Result<Value, Error> {
+pub async fn get_latest_txs() -> Result<Vec<TransactionSummary>, Error> {
     let postgres = STATE.postgres_pool.get().await?;
-    let limit = 30i64;
     let results = postgres
         .query(
-            "
-            WITH ltxs AS (SELECT blocks.chain_id, blocks.number as block_number, blocks.timestamp as block_timestamp, transaction_hash, from_address, to_address, value, error, transaction_index, function_signature, ec_pairing_count, ec_recover_addresses FROM transactions INNER JOIN blocks ON blocks.chain_id = transactions.chain_id AND blocks.number = transactions.block_number ORDER BY transactions.id DESC LIMIT $1)
-            SELECT ltxs.*, sig_names.name as function_name FROM ltxs LEFT JOIN sig_names ON ltxs.function_signature = sig_names.sig ORDER BY block_timestamp DESC, block_number DESC, transaction_index ASC
-            ",
-            &[&limit],
+            &format!(
+                "
+                WITH ltxs AS (SELECT {} FROM {} WHERE blocks.number IS NOT NULL ORDER BY transactions.id DESC LIMIT $1)
+                SELECT * FROM ltxs ORDER BY block_timestamp DESC, block_number DESC, transaction_index ASC
+                ",
+                TransactionSummary::COLUMNS,
+                TRANSACTION_FROM,
+            ),
+            &[&CONFIG.latest.txs_size],
         )
         .await?;
-    let datas = results
+    results
         .iter()
-        .map(|row| {
-            Ok(json!({
-                "chain_id": row.try_get::<_, i64>("chain_id")?,
-                "block_number": row.try_get::<_, i64>("block_number")?,
-                "block_timestamp": row.try_get::<_, i64>("block_timestamp")?,
-                "transaction_hash": row.try_get::<_, String>("transaction_hash")?,
-                "transaction_index": row.try_get::<_, i32>("transaction_index")?,
-                "from_address": row.try_get::<_, String>("from_address")?,
-                "to_address": row.try_get::<_, String>("to_address")?,
-                "value": from_str::<Number>(&row.try_get::<_, String>("value")?)?,
-                "error": row.try_get::<_, Option<String>>("error")?,
-                "ec_pairing_count": row.try_get::<_, i16>("ec_pairing_count")?,
-                "ec_recover_addresses": row.try_get::<_, Vec<String>>("ec_recover_addresses")?,
-                "function_signature": row.try_get::<_, String>("function_signature")?,
-                "function_name": row.try_get::<_, Option<String>>("function_name")?,
-            }))
-        })
-        .collect::<Result<Vec<_>, Error>>()?;
-
-    Ok(json!({
-        "data": datas,
+        .map(TransactionSummary::try_from)
+        .collect::<Result<Vec<_>, _>>()
+}
+
+/// The latest blocks holding indexed transactions, from the window the live feeds share
+#[utoipa::path(
+    get,
+    path = "/latest/blocks",
+    tag = "latest",
+    params(LatestQuery),
+    responses(
+        (status = 200, description = "The latest blocks, newest first", body = BlockListResponse),
+        (status = 400, description = "Invalid limit or chain id", body = ErrorResponse),
+        (status = 404, description = "Unknown chain id", body = ErrorResponse),
+    )
+)]
+pub async fn latest_blocks(
+    State(state): State<Arc<LatestState>>,
+    Query(query): Query<LatestQuery>,
+) -> Result<Json<BlockListResponse>, AppError> {
+    query.validate()?;
+    let filter = resolve_filter(query.chain_id, None, None).await?;
+    let blocks = state.latest_blocks_rx.borrow().clone();
+
+    Ok(Json(
+        blocks
+            .iter()
+            .filter(|block| filter.matches_block(block))
+            .take(query.limit(CONFIG.latest.blocks_size))
+            .cloned()
+            .collect::<Vec<_>>()
+            .into(),
+    ))
+}
+
+/// The latest indexed transactions, from the window the live feeds share
+#[utoipa::path(
+    get,
+    path = "/latest/txs",
+    tag = "latest",
+    params(LatestQuery),
+    responses(
+        (status = 200, description = "The latest transactions, newest first", body = TransactionListResponse),
+        (status = 400, description = "Invalid limit or chain id", body = ErrorResponse),
+        (status = 404, description = "Unknown chain id", body = ErrorResponse),
+    )
+)]
+pub async fn latest_txs(
+    State(state): State<Arc<LatestState>>,
+    Query(query): Query<LatestQuery>,
+) -> Result<Json<TransactionListResponse>, AppError> {
+    query.validate()?;
+    let filter = resolve_filter(query.chain_id, None, None).await?;
+    let txs = state.latest_txs_rx.borrow().clone();
+
+    Ok(Json(
+        txs.iter()
+            .filter(|tx| filter.matches_tx(tx))
+            .take(query.limit(CONFIG.latest.txs_size))
+            .cloned()
+            .collect::<Vec<_>>()
+            .into(),
+    ))
+}
+
+/// `Last-Event-ID` sent by a reconnecting client
+fn last_event_id(headers: &HeaderMap) -> Option<i64> {
+    headers
+        .get("last-event-id")?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Event carrying `items`, with the highest row id among them as the event id
+fn feed_event<T: FeedItem + Serialize>(items: &[T]) -> Result<Event, Error> {
+    let mut event = Event::default();
+    if let Some(id) = items.iter().map(FeedItem::feed_id).max() {
+        event = event.id(id.to_string());
+    }
+    Ok(event.json_data(DataResponse::from(items))?)
+}
+
+/// The window items passing `filter` whenever they change, sent right away when they hold
+/// items newer than `last_id`
+fn snapshot_stream<T, P>(
+    mut rx: watch::Receiver<Arc<Vec<T>>>,
+    last_id: Option<i64>,
+    filter: P,
+) -> impl Stream<Item = Result<Event, Error>>
+where
+    T: FeedItem + Serialize + Clone + Send + Sync + 'static,
+    P: Fn(&T) -> bool + Send + 'static,
+{
+    try_stream! {
+        let mut sent: Option<Value> = None;
+        if let Some(last_id) = last_id {
+            let items = rx.borrow_and_update().clone();
+            let items = items.iter().filter(|item| filter(item)).cloned().collect::<Vec<_>>();
+            if items.iter().any(|item| item.feed_id() > last_id) {
+                yield feed_event(&items)?;
+                sent = Some(to_value(&items)?);
+            }
+        }
+        while rx.changed().await.is_ok() {
+            let items = rx.borrow_and_update().clone();
+            let items = items.iter().filter(|item| filter(item)).cloned().collect::<Vec<_>>();
+            let value = to_value(&items)?;
+            if sent.as_ref() != Some(&value) {
+                yield feed_event(&items)?;
+                sent = Some(value);
+            }
+        }
+    }
+}
+
+/// The items of a broadcast delta not `delivered` yet. A receiver that lagged behind is
+/// caught up from the database with `replay` instead. `None` once the feed is closed.
+pub async fn next_delta<T, R, F>(
+    received: Result<Arc<Vec<T>>, RecvError>,
+    delivered: &mut DeliveredIds,
+    replay: &R,
+) -> Result<Option<Vec<T>>, Error>
+where
+    T: FeedItem + Clone,
+    R: Fn(i64) -> F,
+    F: Future<Output = Result<Vec<T>, Error>>,
+{
+    Ok(Some(match received {
+        Ok(items) => delivered.retain_broadcast(items.to_vec()),
+        Err(RecvError::Lagged(_)) => match delivered.rescan_from() {
+            Some(id) => delivered.retain_new(replay(id).await?),
+            None => Vec::new(),
+        },
+        Err(RecvError::Closed) => return Ok(None),
     }))
 }
 
+/// Only new items, starting with the ones after `last_id` replayed from the database.
+/// A client that falls behind the broadcast is caught up the same way.
+fn delta_stream<T, P, R, F>(
+    mut rx: broadcast::Receiver<Arc<Vec<T>>>,
+    last_id: Option<i64>,
+    filter: P,
+    replay: R,
+) -> impl Stream<Item = Result<Event, Error>>
+where
+    T: FeedItem + Serialize + Clone + Send + Sync + 'static,
+    P: Fn(&T) -> bool + Send + 'static,
+    R: Fn(i64) -> F + Send + 'static,
+    F: Future<Output = Result<Vec<T>, Error>> + Send,
+{
+    try_stream! {
+        // every item already looked at, whether or not it passed the filter
+        let mut delivered = DeliveredIds::new(last_id);
+        if let Some(id) = last_id {
+            let items = delivered.retain_new(replay(id).await?);
+            let items = items.into_iter().filter(|item| filter(item)).collect::<Vec<_>>();
+            if !items.is_empty() {
+                yield feed_event(&items)?;
+            }
+        }
+        while let Some(items) = next_delta(rx.recv().await, &mut delivered, &replay).await? {
+            let items = items.into_iter().filter(|item| filter(item)).collect::<Vec<_>>();
+            if !items.is_empty() {
+                yield feed_event(&items)?;
+            }
+        }
+    }
+}
+
+/// Streams the latest blocks holding indexed transactions whenever they change
+///
+/// Each event id is the highest block id it carries. A client reconnecting with
+/// `Last-Event-ID` gets the current window right away in `snapshot` mode, or up to
+/// 100 missed blocks replayed in `delta` mode.
+#[utoipa::path(
+    get,
+    path = "/latest/blocks/sse",
+    tag = "latest",
+    params(
+        FeedQuery,
+        ("Last-Event-ID" = Option<i64>, Header, description = "Id of the last event received before reconnecting"),
+    ),
+    responses(
+        (status = 200, description = "Server-sent events, each carrying the latest or the new blocks", body = BlockListResponse, content_type = "text/event-stream"),
+    )
+)]
 pub async fn latest_block_sse(
     State(state): State<Arc<LatestState>>,
-) -> Sse<impl Stream<Item = Result<Event, Error>>> {
-    let mut rx = state.latest_blocks_rx.clone();
-    Sse::new(try_stream! {
-        while let Ok(()) = rx.changed().await {
-            let data = state.latest_blocks_rx.borrow().clone();
-            yield Event::default().json_data(data)?;
+    Query(query): Query<FeedQuery>,
+    headers: HeaderMap,
+) -> Sse<BoxStream<'static, Result<Event, Error>>> {
+    let last_id = last_event_id(&headers);
+    let stream = match query.mode.unwrap_or_default() {
+        FeedMode::Snapshot => {
+            snapshot_stream(state.latest_blocks_rx.clone(), last_id, |_| true).boxed()
         }
-    })
-    .keep_alive(KeepAlive::default())
+        FeedMode::Delta => delta_stream(
+            state.subscribe_blocks(),
+            last_id,
+            |_| true,
+            get_blocks_since,
+        )
+        .boxed(),
+    };
+    Sse::new(stream).keep_alive(KeepAlive::default())
 }
 
+/// Streams the latest indexed transactions whenever they change
+///
+/// Each event id is the highest transaction id it carries. A client reconnecting with
+/// `Last-Event-ID` gets the current window right away in `snapshot` mode, or up to
+/// 1000 missed transactions replayed in `delta` mode.
+///
+/// `chain_id`, `address` and `tag` narrow the feed to matching transactions, with the
+/// addresses carrying `tag` looked up once when connecting. As a snapshot only holds
+/// the matches among the latest transactions of all chains, 30 by default, a filtered
+/// feed defaults to `delta` mode.
+#[utoipa::path(
+    get,
+    path = "/latest/txs/sse",
+    tag = "latest",
+    params(
+        FeedQuery,
+        TxFeedFilter,
+        ("Last-Event-ID" = Option<i64>, Header, description = "Id of the last event received before reconnecting"),
+    ),
+    responses(
+        (status = 200, description = "Server-sent events, each carrying the latest or the new transactions", body = TransactionListResponse, content_type = "text/event-stream"),
+        (status = 400, description = "Invalid address, chain id or tag", body = ErrorResponse),
+        (status = 404, description = "Unknown chain id", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
 pub async fn latest_txs_sse(
     State(state): State<Arc<LatestState>>,
-) -> Sse<impl Stream<Item = Result<Event, Error>>> {
-    let mut rx = state.latest_txs_rx.clone();
-    Sse::new(try_stream! {
-        while let Ok(()) = rx.changed().await {
-            let data = state.latest_txs_rx.borrow().clone();
-            yield Event::default().json_data(data)?;
+    Query(query): Query<FeedQuery>,
+    Query(filter): Query<TxFeedFilter>,
+    headers: HeaderMap,
+) -> Result<Sse<BoxStream<'static, Result<Event, Error>>>, AppError> {
+    let mode = query.mode.unwrap_or(
+        if filter.chain_id.is_some() || filter.address.is_some() || filter.tag.is_some() {
+            FeedMode::Delta
+        } else {
+            FeedMode::Snapshot
+        },
+    );
+    let filter = resolve_filter(
+        filter.chain_id,
+        filter.address.as_deref(),
+        filter.tag.as_deref(),
+    )
+    .await?;
+    let matches = move |tx: &TransactionSummary| filter.matches_tx(tx);
+
+    let last_id = last_event_id(&headers);
+    let stream = match mode {
+        FeedMode::Snapshot => {
+            snapshot_stream(state.latest_txs_rx.clone(), last_id, matches).boxed()
         }
-    })
-    .keep_alive(KeepAlive::default())
+        FeedMode::Delta => {
+            delta_stream(state.subscribe_txs(), last_id, matches, get_txs_since).boxed()
+        }
+    };
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Item(i64);
+
+    impl FeedItem for Item {
+        fn feed_id(&self) -> i64 {
+            self.0
+        }
+    }
+
+    async fn replay(id: i64) -> Result<Vec<Item>, Error> {
+        Ok(((id + 1)..=(id + 3)).map(Item).collect())
+    }
+
+    #[tokio::test]
+    async fn deltas_skip_items_already_replayed() {
+        let mut delivered = DeliveredIds::new(Some(1));
+        delivered.retain_new(vec![Item(2)]);
+        let items = next_delta(
+            Ok(Arc::new(vec![Item(2), Item(3)])),
+            &mut delivered,
+            &replay,
+        );
+        assert_eq!(items.await.unwrap(), Some(vec![Item(3)]));
+    }
+
+    #[tokio::test]
+    async fn lag_replays_from_the_trailing_ids() {
+        let mut delivered = DeliveredIds::default();
+        delivered.retain_new(vec![Item(200)]);
+        let items = next_delta(Err(RecvError::Lagged(2)), &mut delivered, &replay).await;
+        let from = 200 - TRAILING_IDS;
+        assert_eq!(
+            items.unwrap(),
+            Some(vec![Item(from + 1), Item(from + 2), Item(from + 3)])
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_replay_is_an_error() {
+        let mut delivered = DeliveredIds::new(Some(5));
+        let failing = |_| async { Err::<Vec<Item>, _>(anyhow!("database unavailable")) };
+        let items = next_delta(Err(RecvError::Lagged(2)), &mut delivered, &failing).await;
+        assert!(items.is_err());
+        assert_eq!(delivered.rescan_from(), Some(5));
+    }
+
+    #[tokio::test]
+    async fn closed_feed_ends() {
+        let mut delivered = DeliveredIds::default();
+        let items = next_delta(Err(RecvError::Closed), &mut delivered, &replay).await;
+        assert_eq!(items.unwrap(), None);
+    }
 }
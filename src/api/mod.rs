@@ -1,18 +1,64 @@
-use axum::Router;
+use axum::{middleware, Router};
+
+use crate::middleware::ChainMetadataMiddleware;
 
 pub mod address;
 pub mod block;
+pub mod chain;
+pub mod export;
 pub mod latest;
+pub mod openapi;
+pub mod search;
 pub mod stats;
 pub mod tag;
 pub mod transaction;
+pub mod ws;
+
+/// Every path [`routes`] serves, to be kept in sync with it. The tests check that each one is
+/// routed and documented in the OpenAPI spec.
+pub const ROUTES: &[&str] = &[
+    "/tx/:hash",
+    "/tx/:hash/all",
+    "/block/:chain-id/:block-number",
+    "/block/:chain-id/:block-number/txs",
+    "/address/:address",
+    "/address/:address/summary",
+    "/address/proxy/:address",
+    "/latest/blocks",
+    "/latest/txs",
+    "/latest/blocks/sse",
+    "/latest/txs/sse",
+    "/tag/all",
+    "/tag/all_by_chain",
+    "/tag/:address",
+    "/tag/tags/:tag",
+    "/stats/tx_count",
+    "/stats/timeseries",
+    "/stats/top/:kind",
+    "/stats/gas",
+    "/chains",
+    "/chains/:chain-id",
+    "/search",
+    "/export/transactions",
+    "/ws",
+    "/openapi.json",
+    "/docs",
+];
 
 pub fn routes() -> Router<()> {
+    let latest = latest::LatestState::spawn();
+
     Router::new()
         .nest("/tx", transaction::routes())
         .nest("/block", block::routes())
         .nest("/address", address::routes())
-        .nest("/latest", latest::routes())
+        .nest("/latest", latest::routes(latest.clone()))
         .nest("/tag", tag::routes())
         .nest("/stats", stats::routes())
+        .nest("/chains", chain::routes())
+        .nest("/search", search::routes())
+        .nest("/export", export::routes())
+        .nest("/ws", ws::routes(latest))
+        .layer(middleware::from_fn(ChainMetadataMiddleware::handler))
+        .merge(openapi::routes())
 }
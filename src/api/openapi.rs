@@ -0,0 +1,176 @@
+use axum::{response::Html, routing::get, Json, Router};
+use utoipa::{
+    openapi::security::{ApiKey, ApiKeyValue, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use crate::{
+    api::{address, block, chain, export, latest, search, stats, tag, transaction, ws},
+    error::{ErrorBody, ErrorCode, ErrorResponse},
+    types::{
+        AddressFilter, AddressRole, AddressSummary, AddressSummaryResponse, AddressTransaction,
+        AddressTransactionsResponse, Block, BlockListResponse, BlockResponse, BlockSummary,
+        ChainActivity, ChainInfo, ChainListResponse, ChainResponse, ChainTags,
+        ChainTagsListResponse, Cursors, DecodedInput, DecodedParam, ExportFormat, FeedMode,
+        FunctionMatch, GasGroup, GasResponse, GasStats, HistogramBucket, Metric, NativeCurrency,
+        Pagination, ProxyDestination, ProxyDestinationListResponse, RoleCounts, SearchKind,
+        SearchResponse, SearchResult, StatsInterval, TagCount, TagCountListResponse, TagEntry,
+        TagEntryListResponse, TagEntryPageResponse, TimeseriesPoint, TimeseriesResponse, TopEntry,
+        TopKind, TopOrder, TopResponse, Transaction, TransactionExportFilter,
+        TransactionListResponse, TransactionMatchesResponse, TransactionResponse,
+        TransactionSummary, TxCount, TxCountListResponse, TxStatus,
+    },
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "evmtrace API",
+        description = "Requests are rate limited per API key, sent as `X-API-Key` or `?api_key=`, or per IP without one. \
+            Responses carry `X-RateLimit-Limit`, `X-RateLimit-Remaining` and `X-RateLimit-Reset` headers, \
+            and a caller over its quota gets a 429 `too_many_requests` error."
+    ),
+    servers((url = "/api/v1")),
+    modifiers(&ApiKeyAddon),
+    paths(
+        transaction::tx_hash,
+        transaction::tx_hash_all,
+        block::block,
+        block::block_txs,
+        address::address,
+        address::proxy_address,
+        address::address_summary,
+        latest::latest_blocks,
+        latest::latest_txs,
+        latest::latest_block_sse,
+        latest::latest_txs_sse,
+        ws::ws,
+        tag::all_tags,
+        tag::tag_by_chain,
+        tag::tag_address,
+        tag::tag,
+        stats::tx_count,
+        stats::timeseries,
+        stats::top,
+        stats::gas,
+        chain::chains,
+        chain::chain,
+        search::search,
+        export::transactions,
+    ),
+    components(schemas(
+        Transaction,
+        TransactionSummary,
+        Block,
+        BlockSummary,
+        ProxyDestination,
+        TagEntry,
+        TagCount,
+        ChainTags,
+        TxCount,
+        ChainInfo,
+        NativeCurrency,
+        Pagination,
+        Cursors,
+        AddressFilter,
+        AddressRole,
+        AddressTransaction,
+        TxStatus,
+        TransactionExportFilter,
+        ExportFormat,
+        DecodedInput,
+        DecodedParam,
+        FeedMode,
+        Metric,
+        StatsInterval,
+        TimeseriesPoint,
+        TimeseriesResponse,
+        TopKind,
+        TopOrder,
+        TopEntry,
+        TopResponse,
+        GasGroup,
+        HistogramBucket,
+        GasStats,
+        GasResponse,
+        SearchKind,
+        SearchResult,
+        SearchResponse,
+        FunctionMatch,
+        AddressSummary,
+        AddressSummaryResponse,
+        RoleCounts,
+        ChainActivity,
+        TagEntryPageResponse,
+        AddressTransactionsResponse,
+        TransactionResponse,
+        TransactionMatchesResponse,
+        TransactionListResponse,
+        BlockResponse,
+        BlockListResponse,
+        ProxyDestinationListResponse,
+        TagEntryListResponse,
+        TagCountListResponse,
+        ChainTagsListResponse,
+        TxCountListResponse,
+        ChainResponse,
+        ChainListResponse,
+        ErrorCode,
+        ErrorBody,
+        ErrorResponse,
+    )),
+    tags(
+        (name = "transaction", description = "Transaction lookup"),
+        (name = "block", description = "Blocks and their transactions"),
+        (name = "address", description = "Address history and proxies"),
+        (name = "latest", description = "Live feeds of the latest blocks and transactions"),
+        (name = "tag", description = "Address tags"),
+        (name = "stats", description = "Aggregated statistics"),
+        (name = "search", description = "Search across transactions, addresses, blocks, tags and functions"),
+        (name = "export", description = "Bulk exports, authenticated by API key"),
+        (name = "chain", description = "Chain registry. Any endpoint accepts `with_chain=true` to embed a `chain` object next to each `chain_id`"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Declares the `api_key` scheme referenced by authenticated endpoints
+struct ApiKeyAddon;
+
+impl Modify for ApiKeyAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "api_key",
+                SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-API-Key"))),
+            );
+        }
+    }
+}
+
+const REDOC_HTML: &str = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <title>evmtrace API</title>
+    <meta charset="utf-8" />
+    <meta name="viewport" content="width=device-width, initial-scale=1" />
+  </head>
+  <body>
+    <redoc spec-url="openapi.json"></redoc>
+    <script src="https://cdn.redoc.ly/redoc/v2.1.5/bundles/redoc.standalone.js"></script>
+  </body>
+</html>
+"#;
+
+pub fn routes() -> Router<()> {
+    Router::new()
+        .route("/openapi.json", get(openapi_json))
+        .route("/docs", get(docs))
+}
+
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+pub async fn docs() -> Html<&'static str> {
+    Html(REDOC_HTML)
+}
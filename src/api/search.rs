@@ -0,0 +1,128 @@
+use axum::{extract::State, middleware, routing::get, Json, Router};
+use serde::Deserialize;
+use tokio::try_join;
+use tokio_postgres::types::ToSql;
+use utoipa::IntoParams;
+
+use super::address::summarize;
+use crate::{
+    error::AppError,
+    extract::Query,
+    middleware::{CacheVary, HeavyRateLimitMiddleware, ShortAlwaysCacheMiddleware},
+    state::{AppState, STATE},
+    types::{
+        Block, FunctionMatch, SearchQuery, SearchResponse, SearchResult, TagCount,
+        TransactionSummary, TRANSACTION_FROM,
+    },
+};
+
+pub fn routes() -> Router<()> {
+    Router::new()
+        .route("/", get(search))
+        .route_layer(middleware::from_fn_with_state(
+            STATE.clone(),
+            ShortAlwaysCacheMiddleware::<SearchParams>::handler,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            STATE.clone(),
+            HeavyRateLimitMiddleware::handler,
+        ))
+        .with_state(STATE.clone())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchParams {
+    /// A transaction hash, an address, a block number, `chain:number` or a tag or function name prefix
+    pub q: String,
+}
+
+impl CacheVary for SearchParams {
+    fn varies_on(param: &str) -> bool {
+        param == "q"
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/search",
+    tag = "search",
+    params(SearchParams),
+    responses(
+        (status = 200, description = "Results matching the classified input", body = SearchResponse),
+        (status = 400, description = "Empty or malformed input", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn search(
+    Query(params): Query<SearchParams>,
+    State(state): State<AppState>,
+) -> Result<Json<SearchResponse>, AppError> {
+    let query = SearchQuery::parse(&params.q)?;
+    let postgres = state.postgres_pool.get().await?;
+
+    let data = match &query {
+        SearchQuery::Hash(hash) => postgres
+            .query(
+                &format!(
+                    "SELECT {} FROM {} WHERE transactions.transaction_hash = $1 ORDER BY transactions.chain_id",
+                    TransactionSummary::COLUMNS,
+                    TRANSACTION_FROM,
+                ),
+                &[hash],
+            )
+            .await?
+            .iter()
+            .map(|row| Ok(SearchResult::Transaction(TransactionSummary::try_from(row)?)))
+            .collect::<Result<Vec<_>, AppError>>()?,
+        SearchQuery::Address(address) => vec![SearchResult::Address(
+            summarize(&postgres, address.clone()).await?,
+        )],
+        SearchQuery::Block { chain_id, number } => postgres
+            .query(
+                &format!(
+                    "SELECT {} FROM blocks WHERE number = $1 AND ($2::BIGINT IS NULL OR chain_id = $2) ORDER BY chain_id",
+                    Block::COLUMNS
+                ),
+                &[number, chain_id],
+            )
+            .await?
+            .iter()
+            .map(|row| Ok(SearchResult::Block(Block::try_from(row)?)))
+            .collect::<Result<Vec<_>, AppError>>()?,
+        SearchQuery::Text(text) => {
+            let prefix = format!(
+                "{}%",
+                text.replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_")
+            );
+            let params: &[&(dyn ToSql + Sync)] = &[&prefix];
+            let (tags, functions) = try_join!(
+                postgres.query(
+                    "SELECT tag, COUNT(*) AS count FROM tags WHERE tag ILIKE $1 GROUP BY tag ORDER BY 2 DESC LIMIT 10",
+                    params,
+                ),
+                postgres.query(
+                    "SELECT sig, name FROM sig_names WHERE name ILIKE $1 ORDER BY name LIMIT 10",
+                    params,
+                ),
+            )?;
+            tags.iter()
+                .map(|row| Ok(SearchResult::Tag(TagCount::try_from(row)?)))
+                .chain(
+                    functions
+                        .iter()
+                        .map(|row| Ok(SearchResult::Function(FunctionMatch::try_from(row)?))),
+                )
+                .collect::<Result<Vec<_>, AppError>>()?
+        }
+    };
+
+    Ok(Json(SearchResponse {
+        query: params.q,
+        kind: query.kind(),
+        data,
+    }))
+}
@@ -1,42 +1,324 @@
 use std::time::SystemTime;
 
-use axum::{extract::State, middleware, routing::get, Json, Router};
-use serde_json::{json, Value};
+use axum::{
+    extract::State,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
 
+use super::export::{export_response, row_stream};
 use crate::{
+    chains::CHAINS,
     error::AppError,
-    middleware::LongAlwaysCacheMiddleware,
+    extract::{Path, Query},
+    middleware::{HeavyRateLimitMiddleware, LongAlwaysCacheMiddleware},
     state::{AppState, STATE},
+    types::{
+        checksum_address, time_range, ExportFormat, GasGroup, GasQuery, GasResponse, GasStats,
+        Metric, StatsInterval, TimeseriesPoint, TimeseriesQuery, TimeseriesResponse, TopEntry,
+        TopKind, TopQuery, TopResponse, TxCount, TxCountListResponse,
+    },
 };
 
 pub fn routes() -> Router<()> {
     Router::new()
-        .route("/tx_count", get(tx_count))
+        .nest(
+            "/",
+            Router::new().route("/tx_count", get(tx_count)).route_layer(
+                middleware::from_fn_with_state(
+                    STATE.clone(),
+                    LongAlwaysCacheMiddleware::<()>::handler,
+                ),
+            ),
+        )
+        .nest(
+            "/",
+            Router::new()
+                .route("/timeseries", get(timeseries))
+                .route("/top/:kind", get(top))
+                .route("/gas", get(gas))
+                .route_layer(middleware::from_fn_with_state(
+                    STATE.clone(),
+                    LongAlwaysCacheMiddleware::<(TimeseriesQuery, TopQuery, GasQuery)>::handler,
+                )),
+        )
         .route_layer(middleware::from_fn_with_state(
             STATE.clone(),
-            LongAlwaysCacheMiddleware::<false>::handler,
+            HeavyRateLimitMiddleware::handler,
         ))
         .with_state(STATE.clone())
 }
 
-pub async fn tx_count(State(state): State<AppState>) -> Result<Json<Value>, AppError> {
+/// Current unix timestamp, the default end of statistics ranges
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[utoipa::path(
+    get,
+    path = "/stats/tx_count",
+    tag = "stats",
+    params(crate::types::FormatQuery),
+    responses(
+        (status = 200, description = "Transaction counts per chain and interval. CSV and NDJSON hold one row per count", content(
+            ("application/json" = TxCountListResponse),
+            ("text/csv" = String),
+            ("application/x-ndjson" = String),
+        )),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn tx_count(
+    format: ExportFormat,
+    State(state): State<AppState>,
+) -> Result<Response, AppError> {
     let postgres = state.postgres_pool.get().await?;
-    let results = postgres.query("SELECT interval_start AS date, chain_id, transaction_count, total_transaction_count FROM transaction_counts_mv ORDER BY 1 DESC", &[]).await?;
+    let statement = "SELECT interval_start AS date, chain_id, transaction_count, total_transaction_count FROM transaction_counts_mv ORDER BY 1 DESC";
+
+    if format != ExportFormat::Json {
+        return Ok(export_response(
+            format,
+            "tx_count",
+            row_stream::<TxCount>(postgres, statement.to_string(), Vec::new()),
+        ));
+    }
+
+    let results = postgres.query(statement, &[]).await?;
     let data = results
         .iter()
-        .map(|result| {
-            Ok(json!((
-                result
-                    .try_get::<_, SystemTime>("date")?
-                    .duration_since(SystemTime::UNIX_EPOCH)?
-                    .as_secs(),
-                result.try_get::<_, i64>("chain_id")?,
-                result.try_get::<_, i64>("transaction_count")?,
-                result.try_get::<_, i64>("total_transaction_count")?,
-            )))
-        })
-        .collect::<Result<Vec<_>, AppError>>()?;
-    Ok(Json(json!({
-        "data": data,
-    })))
+        .map(TxCount::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Json(TxCountListResponse::from(data)).into_response())
+}
+
+/// A metric bucketed by hour, day or week, one series per chain
+///
+/// Buckets are aligned with `date_trunc` in UTC, weeks starting on Monday, and `from` and `to`
+/// are widened to whole buckets. Daily and weekly `tx_count` are read from
+/// `transaction_counts_mv`.
+#[utoipa::path(
+    get,
+    path = "/stats/timeseries",
+    tag = "stats",
+    params(TimeseriesQuery),
+    responses(
+        (status = 200, description = "Metric value per chain and bucket, oldest first", body = TimeseriesResponse),
+        (status = 400, description = "Invalid metric, interval or range", body = ErrorResponse),
+        (status = 404, description = "Unknown chain id", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn timeseries(
+    Query(query): Query<TimeseriesQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<TimeseriesResponse>, AppError> {
+    if let Some(chain_id) = query.chain_id {
+        CHAINS.ensure(chain_id)?;
+    }
+    let (from, to) = query.range(now())?;
+    let interval = query.interval();
+
+    let statement = match (query.metric, interval) {
+        (Metric::TxCount, StatsInterval::Day | StatsInterval::Week) => "
+            SELECT date_trunc($1, interval_start) AS date, chain_id, SUM(transaction_count)::DOUBLE PRECISION AS value
+            FROM transaction_counts_mv
+            WHERE ($2::BIGINT IS NULL OR chain_id = $2) AND interval_start >= to_timestamp($3::BIGINT) AND interval_start < to_timestamp($4::BIGINT)
+            GROUP BY 1, 2 ORDER BY 1, 2
+            "
+        .to_string(),
+        (metric, _) => format!(
+            "
+            SELECT date_trunc($1, to_timestamp(blocks.timestamp) AT TIME ZONE 'UTC') AS date, transactions.chain_id, {}::DOUBLE PRECISION AS value
+            FROM transactions JOIN blocks ON blocks.chain_id = transactions.chain_id AND blocks.number = transactions.block_number
+            WHERE ($2::BIGINT IS NULL OR transactions.chain_id = $2) AND blocks.timestamp >= $3 AND blocks.timestamp < $4
+            GROUP BY 1, 2 ORDER BY 1, 2
+            ",
+            metric.expression()
+        ),
+    };
+
+    let postgres = state.postgres_pool.get().await?;
+    let results = postgres
+        .query(
+            &statement,
+            &[&interval.as_str(), &query.chain_id, &from, &to],
+        )
+        .await?;
+    let data = results
+        .iter()
+        .map(TimeseriesPoint::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Json(TimeseriesResponse {
+        metric: query.metric,
+        interval,
+        from,
+        to,
+        data,
+    }))
+}
+
+/// Most active contracts, senders or functions over a window
+#[utoipa::path(
+    get,
+    path = "/stats/top/{kind}",
+    tag = "stats",
+    params(
+        ("kind" = TopKind, Path, description = "`contracts`, `senders` or `functions`"),
+        TopQuery,
+    ),
+    responses(
+        (status = 200, description = "Entries ranked by `order_by`, highest first", body = TopResponse),
+        (status = 400, description = "Invalid kind, ordering or window", body = ErrorResponse),
+        (status = 404, description = "Unknown chain id", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn top(
+    Path(kind): Path<String>,
+    Query(query): Query<TopQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<TopResponse>, AppError> {
+    let kind = TopKind::parse(&kind)?;
+    if let Some(chain_id) = query.chain_id {
+        CHAINS.ensure(chain_id)?;
+    }
+    let (from, to) = time_range(query.from, query.to, TopQuery::DEFAULT_SPAN, now())?;
+    let order_by = query.order_by.unwrap_or_default();
+
+    let (name, tags) = match kind {
+        TopKind::Functions => (
+            "(SELECT name FROM sig_names WHERE sig_names.sig = top.key LIMIT 1)",
+            "'{}'::TEXT[]",
+        ),
+        TopKind::Contracts | TopKind::Senders => (
+            "NULL::TEXT",
+            "COALESCE((SELECT ARRAY_AGG(DISTINCT tag) FROM tags WHERE tags.address = top.key), '{}')",
+        ),
+    };
+    let statement = format!(
+        "
+        WITH top AS (
+            SELECT {key} AS key, COUNT(*)::BIGINT AS tx_count, COALESCE(SUM(transactions.gas_used_total), 0)::BIGINT AS gas_used, COALESCE(SUM(transactions.ec_pairing_count), 0)::BIGINT AS ec_pairing_count
+            FROM transactions JOIN blocks ON blocks.chain_id = transactions.chain_id AND blocks.number = transactions.block_number
+            WHERE {key} IS NOT NULL AND ($1::BIGINT IS NULL OR transactions.chain_id = $1) AND blocks.timestamp >= $2 AND blocks.timestamp < $3
+            GROUP BY 1 ORDER BY {order} DESC, 1 LIMIT $4
+        )
+        SELECT top.*, {name} AS name, {tags} AS tags FROM top ORDER BY {order} DESC, top.key
+        ",
+        key = kind.column(),
+        order = order_by.column(),
+    );
+
+    let postgres = state.postgres_pool.get().await?;
+    let results = postgres
+        .query(&statement, &[&query.chain_id, &from, &to, &query.limit()])
+        .await?;
+    let data = results
+        .iter()
+        .map(TopEntry::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Json(TopResponse {
+        kind,
+        order_by,
+        from,
+        to,
+        data,
+    }))
+}
+
+/// Gas used per contract or function: percentiles, cost per EC pairing and a histogram
+///
+/// Groups are the `limit` contracts or functions with the most transactions. Each histogram
+/// splits the range between the group's min and max gas used into `buckets` equal buckets.
+#[utoipa::path(
+    get,
+    path = "/stats/gas",
+    tag = "stats",
+    params(GasQuery),
+    responses(
+        (status = 200, description = "Gas statistics per group, most used first", body = GasResponse),
+        (status = 400, description = "Invalid address, grouping or window", body = ErrorResponse),
+        (status = 404, description = "Unknown chain id", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn gas(
+    Query(query): Query<GasQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<GasResponse>, AppError> {
+    if let Some(chain_id) = query.chain_id {
+        CHAINS.ensure(chain_id)?;
+    }
+    let address = query.address.as_deref().map(checksum_address).transpose()?;
+    let (from, to) = time_range(query.from, query.to, GasQuery::DEFAULT_SPAN, now())?;
+    let group_by = query.group_by.unwrap_or_default();
+
+    let name = match group_by {
+        GasGroup::Function => {
+            "(SELECT name FROM sig_names WHERE sig_names.sig = groups.key LIMIT 1)"
+        }
+        GasGroup::Contract => "NULL::TEXT",
+    };
+    let statement = format!(
+        "
+        WITH txs AS (
+            SELECT {key} AS key, transactions.gas_used_total AS gas, transactions.ec_pairing_count AS pairings
+            FROM transactions JOIN blocks ON blocks.chain_id = transactions.chain_id AND blocks.number = transactions.block_number
+            WHERE {key} IS NOT NULL AND ($1::BIGINT IS NULL OR transactions.chain_id = $1) AND blocks.timestamp >= $2 AND blocks.timestamp < $3
+            AND ($4::VARCHAR IS NULL OR transactions.to_address = $4) AND ($5::VARCHAR IS NULL OR transactions.function_signature = $5)
+        ),
+        groups AS (
+            SELECT key, COUNT(*)::BIGINT AS tx_count, MIN(gas)::BIGINT AS min, MAX(gas)::BIGINT AS max,
+            percentile_cont(0.5) WITHIN GROUP (ORDER BY gas) AS median, percentile_cont(0.95) WITHIN GROUP (ORDER BY gas) AS p95,
+            SUM(gas)::DOUBLE PRECISION / NULLIF(SUM(pairings), 0) AS gas_per_pairing
+            FROM txs GROUP BY key ORDER BY tx_count DESC, key LIMIT $6
+        ),
+        histogram AS (
+            SELECT txs.key, width_bucket(txs.gas::DOUBLE PRECISION, groups.min::DOUBLE PRECISION, (groups.max + 1)::DOUBLE PRECISION, $7::INT) AS bucket, COUNT(*)::BIGINT AS count
+            FROM txs JOIN groups ON groups.key = txs.key GROUP BY 1, 2
+        )
+        SELECT groups.*, {name} AS name, $7::INT AS buckets,
+        (SELECT json_agg(json_build_array(bucket, count) ORDER BY bucket) FROM histogram WHERE histogram.key = groups.key) AS histogram
+        FROM groups ORDER BY tx_count DESC, key
+        ",
+        key = group_by.column(),
+    );
+
+    let postgres = state.postgres_pool.get().await?;
+    let results = postgres
+        .query(
+            &statement,
+            &[
+                &query.chain_id,
+                &from,
+                &to,
+                &address,
+                &query.function_signature,
+                &query.limit(),
+                &query.buckets(),
+            ],
+        )
+        .await?;
+    let data = results
+        .iter()
+        .map(GasStats::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Json(GasResponse {
+        group_by,
+        from,
+        to,
+        data,
+    }))
 }
@@ -1,17 +1,21 @@
-use anyhow::Error;
 use axum::{
-    extract::{Path, Query, State},
+    extract::State,
     middleware,
+    response::{IntoResponse, Response},
     routing::get,
     Json, Router,
 };
-use serde_json::{json, Value};
 
+use super::export::{export_response, param_refs, row_stream, SqlParams};
 use crate::{
     error::AppError,
-    middleware::LongAlwaysCacheMiddleware,
+    extract::{Path, Query},
+    middleware::{LightRateLimitMiddleware, LongAlwaysCacheMiddleware},
     state::{AppState, STATE},
-    types::Pagination,
+    types::{
+        checksum_address, ChainTags, ChainTagsListResponse, Cursor, ExportFormat, Pagination,
+        TagCount, TagCountListResponse, TagEntry, TagEntryListResponse, TagEntryPageResponse,
+    },
 };
 
 pub fn routes() -> Router<()> {
@@ -24,7 +28,7 @@ pub fn routes() -> Router<()> {
                 .route("/:address", get(tag_address))
                 .route_layer(middleware::from_fn_with_state(
                     STATE.clone(),
-                    LongAlwaysCacheMiddleware::<false>::handler,
+                    LongAlwaysCacheMiddleware::<()>::handler,
                 )),
         )
         .nest(
@@ -33,13 +37,29 @@ pub fn routes() -> Router<()> {
                 .route("/:tag", get(tag))
                 .route_layer(middleware::from_fn_with_state(
                     STATE.clone(),
-                    LongAlwaysCacheMiddleware::<true>::handler,
+                    LongAlwaysCacheMiddleware::<Pagination>::handler,
                 )),
         )
+        .route_layer(middleware::from_fn_with_state(
+            STATE.clone(),
+            LightRateLimitMiddleware::handler,
+        ))
         .with_state(STATE.clone())
 }
 
-pub async fn tag_by_chain(State(state): State<AppState>) -> Result<Json<Value>, AppError> {
+#[utoipa::path(
+    get,
+    path = "/tag/all_by_chain",
+    tag = "tag",
+    responses(
+        (status = 200, description = "Tag counts grouped by chain", body = ChainTagsListResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn tag_by_chain(
+    State(state): State<AppState>,
+) -> Result<Json<ChainTagsListResponse>, AppError> {
     let postgres = state.postgres_pool.get().await?;
 
     let results = postgres
@@ -53,56 +73,108 @@ pub async fn tag_by_chain(State(state): State<AppState>) -> Result<Json<Value>,
         .await?;
 
     let data = results
-        .into_iter()
-        .map(|row| {
-            Ok::<_, Error>(json!({
-                "chain_id": row.try_get::<_, i64>("chain_id")?,
-                "tags": row.try_get::<_, Value>("tags")?,
-            }))
-        })
+        .iter()
+        .map(ChainTags::try_from)
         .collect::<Result<Vec<_>, _>>()?;
 
-    Ok(Json(json!({ "data": data })))
+    Ok(Json(data.into()))
 }
 
+#[utoipa::path(
+    get,
+    path = "/tag/tags/{tag}",
+    tag = "tag",
+    params(("tag" = String, Path, description = "Tag name"), Pagination, crate::types::FormatQuery),
+    responses(
+        (status = 200, description = "Addresses carrying the tag. CSV and NDJSON hold one row per address", content(
+            ("application/json" = TagEntryPageResponse),
+            ("text/csv" = String),
+            ("application/x-ndjson" = String),
+        )),
+        (status = 400, description = "Invalid pagination", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
 pub async fn tag(
     Path(tag): Path<String>,
     State(state): State<AppState>,
     Query(pagination): Query<Pagination>,
-) -> Result<Json<Value>, AppError> {
+    format: ExportFormat,
+) -> Result<Response, AppError> {
+    pagination.validate()?;
     let postgres = state.postgres_pool.get().await?;
 
-    let results = postgres
-        .query(
-            "SELECT address, ARRAY_AGG(DISTINCT tag) AS tags
+    let cursor = pagination.cursor()?;
+
+    let statement = format!(
+        "WITH addresses AS (
+            -- an address may carry the tag on several chains, its first row positions it
+            SELECT MIN(id)::BIGINT AS id, address
             FROM tags
-            WHERE address IN (
-                SELECT address
-                FROM tags
-                WHERE tag = $1
-                ORDER BY id DESC
-                OFFSET $2
-                LIMIT $3
-            )
-            GROUP BY address",
-            &[&tag, &pagination.offset(), &pagination.limit()],
+            WHERE tag = $1
+            GROUP BY address
+        ), page AS (
+            SELECT id, address
+            FROM addresses
+            WHERE ($4::BIGINT IS NULL OR id < $4) AND ($5::BIGINT IS NULL OR id > $5)
+            ORDER BY id {}
+            OFFSET $2
+            LIMIT $3
         )
-        .await?;
-
+        SELECT page.id, page.address, ARRAY_AGG(DISTINCT tags.tag) AS tags
+        FROM page JOIN tags ON tags.address = page.address
+        GROUP BY page.id, page.address
+        ORDER BY 1 DESC",
+        Cursor::order(cursor),
+    );
+    let params: SqlParams = vec![
+        Box::new(tag.clone()),
+        Box::new(pagination.offset()),
+        Box::new(pagination.limit()),
+        Box::new(Cursor::before(cursor)),
+        Box::new(Cursor::after(cursor)),
+    ];
+
+    if format != ExportFormat::Json {
+        return Ok(export_response(
+            format,
+            &format!("tag-{}", tag),
+            row_stream::<TagEntry>(postgres, statement, params),
+        ));
+    }
+
+    let results = postgres.query(&statement, &param_refs(&params)).await?;
+
+    let ids = results
+        .iter()
+        .map(|row| row.try_get::<_, i64>("id"))
+        .collect::<Result<Vec<_>, _>>()?;
     let data = results
-        .into_iter()
-        .map(|row| {
-            Ok::<_, Error>(json!({
-                "address": row.try_get::<_, String>("address")?,
-                "tags": row.try_get::<_, Vec<String>>("tags")?,
-            }))
-        })
+        .iter()
+        .map(TagEntry::try_from)
         .collect::<Result<Vec<_>, _>>()?;
 
-    Ok(Json(json!({ "data": data })))
+    Ok(Json(TagEntryPageResponse {
+        cursors: pagination.cursors(&ids)?,
+        data,
+    })
+    .into_response())
 }
 
-pub async fn all_tags(State(state): State<AppState>) -> Result<Json<Value>, AppError> {
+#[utoipa::path(
+    get,
+    path = "/tag/all",
+    tag = "tag",
+    responses(
+        (status = 200, description = "Every tag with the number of addresses carrying it", body = TagCountListResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn all_tags(
+    State(state): State<AppState>,
+) -> Result<Json<TagCountListResponse>, AppError> {
     let postgres = state.postgres_pool.get().await?;
 
     let results = postgres
@@ -113,25 +185,36 @@ pub async fn all_tags(State(state): State<AppState>) -> Result<Json<Value>, AppE
         .await?;
 
     let data = results
-        .into_iter()
-        .map(|row| {
-            Ok::<_, Error>(json!({
-                "tag": row.try_get::<_, String>("tag")?,
-                "count": row.try_get::<_, i64>("count")?,
-            }))
-        })
+        .iter()
+        .map(TagCount::try_from)
         .collect::<Result<Vec<_>, _>>()?;
 
-    Ok(Json(json!({ "data": data })))
+    Ok(Json(data.into()))
 }
 
+#[utoipa::path(
+    get,
+    path = "/tag/{address}",
+    tag = "tag",
+    params(("address" = String, Path, description = "Comma separated list of up to 20 addresses")),
+    responses(
+        (status = 200, description = "Tags of each address", body = TagEntryListResponse),
+        (status = 400, description = "Invalid address", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
 pub async fn tag_address(
     Path(address): Path<String>,
     State(state): State<AppState>,
-) -> Result<Json<Value>, AppError> {
+) -> Result<Json<TagEntryListResponse>, AppError> {
+    // tags are stored checksummed
+    let address_list = address
+        .split(',')
+        .take(20)
+        .map(|address| checksum_address(address.trim()))
+        .collect::<Result<Vec<_>, _>>()?;
     let postgres = state.postgres_pool.get().await?;
-    let mut address_list = address.split(",").collect::<Vec<&str>>();
-    address_list.truncate(20);
 
     let results = postgres
         .query(
@@ -141,14 +224,9 @@ pub async fn tag_address(
         .await?;
 
     let data = results
-        .into_iter()
-        .map(|row| {
-            Ok::<_, Error>(json!({
-                "address": row.try_get::<_, String>("address")?,
-                "tags": row.try_get::<_, Vec<String>>("tags")?,
-            }))
-        })
+        .iter()
+        .map(TagEntry::try_from)
         .collect::<Result<Vec<_>, _>>()?;
 
-    Ok(Json(json!({ "data": data })))
+    Ok(Json(data.into()))
 }
@@ -1,61 +1,196 @@
-use axum::{
-    extract::{Path, State},
-    middleware,
-    routing::get,
-    Json, Router,
-};
-use serde_json::{from_str, json, Number, Value};
+use std::collections::HashMap;
+
+use axum::{extract::State, middleware, routing::get, Json, Router};
+use deadpool_postgres::Client;
+use ethers_core::abi::Abi;
+use log::debug;
+use serde::Deserialize;
+use serde_json::{from_value, Value};
+use utoipa::IntoParams;
 
 use crate::{
     error::AppError,
-    middleware::LongAlwaysCacheMiddleware,
+    extract::{Path, Query},
+    middleware::{CacheVary, LightRateLimitMiddleware, LongAlwaysCacheMiddleware},
     state::{State as AppState, STATE},
+    types::{
+        normalize_hash, DecodedInput, Transaction, TransactionMatchesResponse, TransactionResponse,
+        TRANSACTION_FROM,
+    },
 };
 
 pub fn routes() -> Router<()> {
     Router::new()
         .route("/:hash", get(tx_hash))
+        .route("/:hash/all", get(tx_hash_all))
         .route_layer(middleware::from_fn_with_state(
             STATE.clone(),
-            LongAlwaysCacheMiddleware::<false>::handler,
+            LongAlwaysCacheMiddleware::<TxHashQuery>::handler,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            STATE.clone(),
+            LightRateLimitMiddleware::handler,
         ))
         .with_state(STATE.clone())
 }
 
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TxHashQuery {
+    /// Chain to look the hash up on, the first indexed match is returned when unset
+    pub chain_id: Option<i64>,
+}
+
+impl CacheVary for TxHashQuery {
+    fn varies_on(param: &str) -> bool {
+        param == "chain_id"
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/tx/{hash}",
+    tag = "transaction",
+    params(("hash" = String, Path, description = "Transaction hash"), TxHashQuery),
+    responses(
+        (status = 200, description = "Transaction found", body = TransactionResponse),
+        (status = 400, description = "Malformed hash", body = ErrorResponse),
+        (status = 404, description = "Transaction not found", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
 pub async fn tx_hash(
+    Path(hash): Path<String>,
+    Query(query): Query<TxHashQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<TransactionResponse>, AppError> {
+    let hash = normalize_hash(&hash)?;
+    let postgres = state.postgres_pool.get().await?;
+
+    let results = postgres
+        .query(
+            &format!(
+                "SELECT {} FROM {} WHERE transactions.transaction_hash = $1 AND ($2::BIGINT IS NULL OR transactions.chain_id = $2) ORDER BY transactions.id LIMIT 1",
+                Transaction::COLUMNS,
+                TRANSACTION_FROM,
+            ),
+            &[&hash, &query.chain_id],
+        )
+        .await?;
+    let result = results.first().ok_or_else(AppError::not_found)?;
+    let mut transaction = Transaction::try_from(result)?;
+    let abis = contract_abis(&postgres, std::slice::from_ref(&transaction)).await;
+    decode_input(&mut transaction, &abis);
+
+    Ok(Json(transaction.into()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/tx/{hash}/all",
+    tag = "transaction",
+    params(("hash" = String, Path, description = "Transaction hash")),
+    responses(
+        (status = 200, description = "The transaction on every chain it was found on", body = TransactionMatchesResponse),
+        (status = 400, description = "Malformed hash", body = ErrorResponse),
+        (status = 404, description = "Transaction not found on any chain", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+        (status = 503, description = "Database unavailable", body = ErrorResponse),
+    )
+)]
+pub async fn tx_hash_all(
     Path(hash): Path<String>,
     State(state): State<AppState>,
-) -> Result<Json<Value>, AppError> {
+) -> Result<Json<TransactionMatchesResponse>, AppError> {
+    let hash = normalize_hash(&hash)?;
     let postgres = state.postgres_pool.get().await?;
 
     let results = postgres
         .query(
-            "SELECT transactions.chain_id, from_address, to_address, transaction_hash, transaction_index, block_number, blocks.timestamp AS block_timestamp, value, input, gas_used_total, gas_used_first_degree, error, function_signature, sig_names.name AS function_name, ec_pairing_count, ec_recover_count, ec_recover_addresses, closest_address FROM transactions LEFT JOIN sig_names ON transactions.function_signature = sig_names.sig LEFT JOIN blocks ON blocks.chain_id = transactions.chain_id AND blocks.number = transactions.block_number WHERE transaction_hash = $1 LIMIT 1",
+            &format!(
+                "SELECT {} FROM {} WHERE transactions.transaction_hash = $1 ORDER BY transactions.chain_id",
+                Transaction::COLUMNS,
+                TRANSACTION_FROM,
+            ),
             &[&hash],
         )
         .await?;
-    let result = results.get(0).ok_or_else(AppError::not_found)?;
-
-    Ok(Json(json!({
-        "data": {
-            "chain_id": result.try_get::<_, i64>("chain_id")?,
-            "from_address": result.try_get::<_, String>("from_address")?,
-            "to_address": result.try_get::<_, String>("to_address")?,
-            "transaction_hash": result.try_get::<_, String>("transaction_hash")?,
-            "transaction_index": result.try_get::<_, i32>("transaction_index")?,
-            "block_number": result.try_get::<_, i64>("block_number")?,
-            "block_timestamp": result.try_get::<_, i64>("block_timestamp")?,
-            "value": from_str::<Number>(&result.try_get::<_, String>("value")?)?,
-            "input": result.try_get::<_, String>("input")?,
-            "gas_used_total": result.try_get::<_, i64>("gas_used_total")?,
-            "gas_used_first_degree": result.try_get::<_, i64>("gas_used_first_degree")?,
-            "error": result.try_get::<_, Option<String>>("error")?,
-            "function_signature": result.try_get::<_, Option<String>>("function_signature")?,
-            "function_name": result.try_get::<_, Option<String>>("function_name")?,
-            "ec_pairing_count": result.try_get::<_, i16>("ec_pairing_count")?,
-            "ec_recover_count": result.try_get::<_, i16>("ec_recover_count")?,
-            "ec_recover_addresses": result.try_get::<_, Vec<String>>("ec_recover_addresses")?,
-            "closest_address": result.try_get::<_, Vec<String>>("closest_address")?,
+    if results.is_empty() {
+        return Err(AppError::not_found());
+    }
+
+    let mut data = results
+        .iter()
+        .map(Transaction::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    let abis = contract_abis(&postgres, &data).await;
+    data.iter_mut()
+        .for_each(|transaction| decode_input(transaction, &abis));
+
+    Ok(Json(data.into()))
+}
+
+/// ABIs uploaded for the contracts `transactions` call, by chain id and address. A failed lookup
+/// only leaves the decoding to the `sig_names` signatures.
+async fn contract_abis(
+    postgres: &Client,
+    transactions: &[Transaction],
+) -> HashMap<(i64, String), Abi> {
+    let (chain_ids, addresses): (Vec<i64>, Vec<String>) = transactions
+        .iter()
+        .map(|transaction| (transaction.chain_id, transaction.to_address.clone()))
+        .unzip();
+    let rows = match postgres
+        .query(
+            "SELECT chain_id, address, abi FROM contract_abis WHERE (chain_id, address) IN (SELECT * FROM UNNEST($1::BIGINT[], $2::VARCHAR[]))",
+            &[&chain_ids, &addresses],
+        )
+        .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            debug!("Failed to fetch contract ABIs: {}", e);
+            return HashMap::new();
         }
-    })))
+    };
+    rows.iter()
+        .filter_map(|row| {
+            let abi = from_value::<Abi>(row.try_get::<_, Value>("abi").ok()?)
+                .map_err(|e| debug!("Failed to parse a contract ABI: {}", e))
+                .ok()?;
+            Some((
+                (row.try_get("chain_id").ok()?, row.try_get("address").ok()?),
+                abi,
+            ))
+        })
+        .collect()
+}
+
+/// Fills `decoded_input` using the ABI uploaded for the called contract, which names the
+/// parameters, falling back to the `sig_names` signature. Any failure only leaves it empty.
+fn decode_input(transaction: &mut Transaction, abis: &HashMap<(i64, String), Abi>) {
+    let from_abi = abis
+        .get(&(transaction.chain_id, transaction.to_address.clone()))
+        .and_then(|abi| {
+            DecodedInput::from_abi(abi, &transaction.input)
+                .map_err(|e| {
+                    debug!(
+                        "Failed to decode {} with its ABI: {}",
+                        transaction.transaction_hash, e
+                    )
+                })
+                .ok()
+        });
+    transaction.decoded_input = from_abi.or_else(|| {
+        let signature = transaction.function_name.as_deref()?;
+        DecodedInput::from_signature(signature, &transaction.input)
+            .map_err(|e| {
+                debug!(
+                    "Failed to decode {} with {}: {}",
+                    transaction.transaction_hash, signature, e
+                )
+            })
+            .ok()
+    });
 }
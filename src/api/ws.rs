@@ -0,0 +1,221 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        State, WebSocketUpgrade,
+    },
+    middleware,
+    response::Response,
+    routing::get,
+    Router,
+};
+use log::warn;
+use serde_json::{from_str, from_value, json, to_string, to_value, Value};
+
+use super::latest::{get_blocks_since, get_txs_since, next_delta, resolve_filter, LatestState};
+use crate::{
+    middleware::LightRateLimitMiddleware,
+    state::STATE,
+    types::{
+        DeliveredIds, LiveFilter, RpcError, RpcRequest, RpcResponse, SubscriptionNotification,
+        SubscriptionParams, SubscriptionTopic,
+    },
+};
+
+/// Most subscriptions a single connection may hold at once
+const MAX_SUBSCRIPTIONS: usize = 32;
+
+pub fn routes(state: Arc<LatestState>) -> Router<()> {
+    Router::new()
+        .route("/", get(ws))
+        .route_layer(middleware::from_fn_with_state(
+            STATE.clone(),
+            LightRateLimitMiddleware::handler,
+        ))
+        .with_state(state)
+}
+
+/// Subscribes to the live feeds over a websocket
+///
+/// Requests follow JSON-RPC 2.0. `{"jsonrpc": "2.0", "id": 1, "method": "subscribe", "params": [topic, {"chain_id", "address", "tag"}]}`
+/// returns the subscription id as `result`, with `topic` one of `newBlocks`, `newTxs`,
+/// `addressActivity` (requires `address`) or `tagActivity` (requires `tag`).
+/// `{"method": "unsubscribe", "params": [id]}` returns whether the subscription existed.
+///
+/// Only items added since the previous poll are pushed, as
+/// `{"jsonrpc": "2.0", "method": "subscription", "params": {"subscription": id, "result": [...]}}`.
+/// A connection that falls behind the feeds gets the items it missed replayed.
+#[utoipa::path(
+    get,
+    path = "/ws",
+    tag = "latest",
+    responses(
+        (status = 101, description = "Switching to the websocket protocol"),
+    )
+)]
+pub async fn ws(upgrade: WebSocketUpgrade, State(state): State<Arc<LatestState>>) -> Response {
+    upgrade.on_upgrade(move |socket| handle_socket(socket, state))
+}
+
+struct Subscription {
+    topic: SubscriptionTopic,
+    filter: LiveFilter,
+}
+
+async fn handle_socket(mut socket: WebSocket, state: Arc<LatestState>) {
+    let mut blocks_rx = state.subscribe_blocks();
+    let mut txs_rx = state.subscribe_txs();
+    let mut subscriptions = HashMap::<u64, Subscription>::new();
+    let mut next_id = 1u64;
+    // items sent from each feed, to replay what a lag skipped
+    let mut delivered_blocks = DeliveredIds::default();
+    let mut delivered_txs = DeliveredIds::default();
+
+    loop {
+        let messages = tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    vec![to_value(handle_request(&text, &mut subscriptions, &mut next_id).await)]
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+            blocks = blocks_rx.recv() => match next_delta(blocks, &mut delivered_blocks, &get_blocks_since).await {
+                Ok(Some(blocks)) => notify(&subscriptions, |subscription| {
+                    (subscription.topic == SubscriptionTopic::NewBlocks).then(|| {
+                        to_value(
+                            blocks
+                                .iter()
+                                .filter(|block| subscription.filter.matches_block(block))
+                                .collect::<Vec<_>>(),
+                        )
+                    })
+                }),
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("Failed to replay missed deltas to a websocket client: {}", e);
+                    continue;
+                }
+            },
+            txs = txs_rx.recv() => match next_delta(txs, &mut delivered_txs, &get_txs_since).await {
+                Ok(Some(txs)) => notify(&subscriptions, |subscription| {
+                    (subscription.topic != SubscriptionTopic::NewBlocks).then(|| {
+                        to_value(
+                            txs.iter()
+                                .filter(|tx| subscription.filter.matches_tx(tx))
+                                .collect::<Vec<_>>(),
+                        )
+                    })
+                }),
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("Failed to replay missed deltas to a websocket client: {}", e);
+                    continue;
+                }
+            },
+        };
+
+        for message in messages {
+            let sent = match message.and_then(|message| to_string(&message)) {
+                Ok(message) => socket.send(Message::Text(message)).await,
+                Err(e) => {
+                    warn!("Failed to serialize websocket message: {}", e);
+                    continue;
+                }
+            };
+            if sent.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Notifications for every subscription `select` returns a non-empty delta for
+fn notify<F>(
+    subscriptions: &HashMap<u64, Subscription>,
+    select: F,
+) -> Vec<Result<Value, serde_json::Error>>
+where
+    F: Fn(&Subscription) -> Option<Result<Value, serde_json::Error>>,
+{
+    subscriptions
+        .iter()
+        .filter_map(|(id, subscription)| match select(subscription)? {
+            Ok(Value::Array(items)) if items.is_empty() => None,
+            Ok(items) => Some(to_value(SubscriptionNotification::new(*id, items))),
+            Err(e) => Some(Err(e)),
+        })
+        .collect()
+}
+
+async fn handle_request(
+    text: &str,
+    subscriptions: &mut HashMap<u64, Subscription>,
+    next_id: &mut u64,
+) -> RpcResponse {
+    let request = match from_str::<RpcRequest>(text) {
+        Ok(request) => request,
+        Err(e) => return RpcResponse::new(Value::Null, Err(RpcError::parse_error(e))),
+    };
+
+    let result = match request.method.as_str() {
+        "subscribe" if subscriptions.len() >= MAX_SUBSCRIPTIONS => Err(RpcError::invalid_params(
+            format!("At most {} subscriptions per connection", MAX_SUBSCRIPTIONS),
+        )),
+        "subscribe" => subscribe(request.params).await.map(|subscription| {
+            let id = *next_id;
+            *next_id += 1;
+            subscriptions.insert(id, subscription);
+            json!(id)
+        }),
+        "unsubscribe" => match request.params.first().cloned().map(from_value::<u64>) {
+            Some(Ok(id)) => Ok(json!(subscriptions.remove(&id).is_some())),
+            Some(Err(e)) => Err(RpcError::invalid_params(e)),
+            None => Err(RpcError::invalid_params("Missing subscription id")),
+        },
+        method => Err(RpcError::method_not_found(method)),
+    };
+
+    RpcResponse::new(request.id, result)
+}
+
+async fn subscribe(params: Vec<Value>) -> Result<Subscription, RpcError> {
+    let mut params = params.into_iter();
+    let topic = from_value::<SubscriptionTopic>(
+        params
+            .next()
+            .ok_or_else(|| RpcError::invalid_params("Missing topic"))?,
+    )
+    .map_err(RpcError::invalid_params)?;
+    let params = match params.next() {
+        Some(params) => {
+            from_value::<SubscriptionParams>(params).map_err(RpcError::invalid_params)?
+        }
+        None => SubscriptionParams::default(),
+    };
+
+    let (address, tag) =
+        match topic {
+            SubscriptionTopic::NewBlocks | SubscriptionTopic::NewTxs => (None, None),
+            SubscriptionTopic::AddressActivity => (
+                Some(params.address.as_deref().ok_or_else(|| {
+                    RpcError::invalid_params("addressActivity requires an address")
+                })?),
+                None,
+            ),
+            SubscriptionTopic::TagActivity => (
+                None,
+                Some(
+                    params
+                        .tag
+                        .as_deref()
+                        .ok_or_else(|| RpcError::invalid_params("tagActivity requires a tag"))?,
+                ),
+            ),
+        };
+
+    let filter = resolve_filter(params.chain_id, address, tag).await?;
+
+    Ok(Subscription { topic, filter })
+}
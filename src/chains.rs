@@ -0,0 +1,58 @@
+use std::{collections::BTreeMap, fs::read_to_string};
+
+use anyhow::{anyhow, Result};
+use log::info;
+use once_cell::sync::Lazy;
+use serde_json::from_str;
+
+use crate::{
+    config::CONFIG,
+    error::{AppError, ErrorCode},
+    types::ChainInfo,
+};
+
+/// Chains indexed by default, used when `CHAINS_CONFIG` is not set
+const DEFAULT_CHAINS: &str = include_str!("../chains.json");
+
+pub static CHAINS: Lazy<ChainRegistry> =
+    Lazy::new(|| ChainRegistry::new().expect("Failed to load chain registry"));
+
+pub struct ChainRegistry {
+    chains: BTreeMap<i64, ChainInfo>,
+}
+
+impl ChainRegistry {
+    pub fn new() -> Result<Self> {
+        let chains = match &CONFIG.chains_config {
+            Some(path) => {
+                info!("Loading chain registry from {}", path);
+                from_str::<Vec<ChainInfo>>(&read_to_string(path)?)?
+            }
+            None => from_str::<Vec<ChainInfo>>(DEFAULT_CHAINS)?,
+        };
+        Ok(Self {
+            chains: chains
+                .into_iter()
+                .map(|chain| (chain.chain_id, chain))
+                .collect(),
+        })
+    }
+
+    pub fn get(&self, chain_id: i64) -> Option<&ChainInfo> {
+        self.chains.get(&chain_id)
+    }
+
+    /// Rejects chain ids missing from the registry with a 404
+    pub fn ensure(&self, chain_id: i64) -> Result<&ChainInfo, AppError> {
+        self.get(chain_id).ok_or_else(|| {
+            AppError::new(
+                ErrorCode::NotFound,
+                anyhow!("Unknown chain id {}", chain_id),
+            )
+        })
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = &ChainInfo> {
+        self.chains.values()
+    }
+}
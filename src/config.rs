@@ -20,6 +20,49 @@ strike! {
         pub redis: String,
         pub port: u16,
         pub is_dev: bool,
+        /// Path to a JSON chain registry, the bundled `chains.json` is used when unset
+        pub chains_config: Option<String>,
+        /// Keys accepted by authenticated endpoints such as `/export`, as `key` or `key:tier`
+        pub api_keys: Vec<String>,
+        /// Path to a JSON object mapping API keys to their tier, merged with `api_keys`
+        pub api_keys_file: Option<String>,
+        /// Whether the client IP is read from `X-Forwarded-For`, only safe behind a proxy setting it
+        pub trust_forwarded_for: bool,
+        /// Proxies appending to `X-Forwarded-For` in front of the API, the client IP is the entry
+        /// that many positions from the right
+        pub trusted_proxy_hops: usize,
+        /// Exports a single API key may stream at once
+        pub export_concurrency: usize,
+        /// Exports streamed at once over all API keys and address downloads, each holding a
+        /// Postgres connection while it runs
+        pub export_total_concurrency: usize,
+        /// Blocks below the indexed head of a chain after which a block is no longer reorganized
+        /// nor indexed, and its responses are cached as immutable
+        pub finality_depth: i64,
+        pub latest:
+            pub struct {
+                /// Number of blocks in the latest blocks window
+                pub blocks_size: i64,
+                /// Number of transactions in the latest transactions window
+                pub txs_size: i64,
+                /// Seconds between polls while not listening for notifications
+                pub poll_interval: u64,
+                /// Postgres channel the indexer NOTIFYs on new transactions, the feeds only poll when unset
+                pub notify_channel: Option<String>,
+            }
+        ,
+        pub rate_limit:
+            pub struct {
+                /// Seconds of a rate limit window
+                pub window: u64,
+                /// Cost units per window of requests without an API key, counted per IP
+                pub anonymous: u32,
+                /// Cost units per window of a `standard` API key
+                pub standard: u32,
+                /// Cost units per window of a `partner` API key
+                pub partner: u32,
+            }
+        ,
     }
 }
 
@@ -33,11 +76,72 @@ impl Config {
                 db: var("POSTGRES_DB").expect("POSTGRES_DB must be set"),
             },
             redis: var("REDIS_URL").expect("REDIS_URL must be set"),
-            port: var("PORT")
+            port: optional_var("PORT")
                 .unwrap_or("8080".to_string())
                 .parse()
                 .expect("PORT must be a number"),
-            is_dev: var("MODE").map(|m| m == "dev").unwrap_or_default(),
+            is_dev: optional_var("MODE").map(|m| m == "dev").unwrap_or_default(),
+            chains_config: optional_var("CHAINS_CONFIG"),
+            api_keys: optional_var("API_KEYS")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(str::to_string)
+                .collect(),
+            api_keys_file: optional_var("API_KEYS_FILE"),
+            trust_forwarded_for: optional_var("TRUST_FORWARDED_FOR")
+                .map(|t| t == "true")
+                .unwrap_or_default(),
+            trusted_proxy_hops: optional_var("TRUSTED_PROXY_HOPS")
+                .unwrap_or("1".to_string())
+                .parse()
+                .expect("TRUSTED_PROXY_HOPS must be a number"),
+            export_concurrency: optional_var("EXPORT_CONCURRENCY")
+                .unwrap_or("2".to_string())
+                .parse()
+                .expect("EXPORT_CONCURRENCY must be a number"),
+            export_total_concurrency: optional_var("EXPORT_TOTAL_CONCURRENCY")
+                .unwrap_or("8".to_string())
+                .parse()
+                .expect("EXPORT_TOTAL_CONCURRENCY must be a number"),
+            finality_depth: optional_var("FINALITY_DEPTH")
+                .unwrap_or("64".to_string())
+                .parse()
+                .expect("FINALITY_DEPTH must be a number"),
+            latest: Latest {
+                blocks_size: optional_var("LATEST_BLOCKS_SIZE")
+                    .unwrap_or("20".to_string())
+                    .parse()
+                    .expect("LATEST_BLOCKS_SIZE must be a number"),
+                txs_size: optional_var("LATEST_TXS_SIZE")
+                    .unwrap_or("30".to_string())
+                    .parse()
+                    .expect("LATEST_TXS_SIZE must be a number"),
+                poll_interval: optional_var("LATEST_POLL_INTERVAL")
+                    .unwrap_or("3".to_string())
+                    .parse()
+                    .expect("LATEST_POLL_INTERVAL must be a number"),
+                notify_channel: optional_var("LATEST_NOTIFY_CHANNEL"),
+            },
+            rate_limit: RateLimit {
+                window: optional_var("RATE_LIMIT_WINDOW")
+                    .unwrap_or("60".to_string())
+                    .parse()
+                    .expect("RATE_LIMIT_WINDOW must be a number"),
+                anonymous: optional_var("RATE_LIMIT_ANONYMOUS")
+                    .unwrap_or("120".to_string())
+                    .parse()
+                    .expect("RATE_LIMIT_ANONYMOUS must be a number"),
+                standard: optional_var("RATE_LIMIT_STANDARD")
+                    .unwrap_or("1200".to_string())
+                    .parse()
+                    .expect("RATE_LIMIT_STANDARD must be a number"),
+                partner: optional_var("RATE_LIMIT_PARTNER")
+                    .unwrap_or("12000".to_string())
+                    .parse()
+                    .expect("RATE_LIMIT_PARTNER must be a number"),
+            },
         }
     }
 
@@ -46,6 +150,11 @@ impl Config {
     }
 }
 
+/// Value of an optional environment variable, an empty one counting as unset
+fn optional_var(key: &str) -> Option<String> {
+    var(key).ok().filter(|value| !value.is_empty())
+}
+
 impl From<&Config> for PostgresConfig {
     fn from(val: &Config) -> Self {
         PostgresConfig {
@@ -60,3 +169,17 @@ impl From<&Config> for PostgresConfig {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_optional_vars_are_unset() {
+        std::env::set_var("ZKSCAN_TEST_EMPTY", "");
+        std::env::set_var("ZKSCAN_TEST_SET", "42");
+        assert_eq!(optional_var("ZKSCAN_TEST_EMPTY"), None);
+        assert_eq!(optional_var("ZKSCAN_TEST_UNSET"), None);
+        assert_eq!(optional_var("ZKSCAN_TEST_SET"), Some("42".to_string()));
+    }
+}
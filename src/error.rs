@@ -1,31 +1,164 @@
+use std::{
+    error::Error as StdError,
+    fmt::{self, Display},
+};
+
 use anyhow::{anyhow, Error};
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
+    Json,
 };
+use deadpool_postgres::PoolError;
+use log::error;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Stable, machine readable kind of an error, returned as `error.code`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidAddress,
+    InvalidChainId,
+    InvalidHash,
+    InvalidParameter,
+    NotFound,
+    PaginationOutOfRange,
+    Unauthorized,
+    TooManyRequests,
+    UpstreamUnavailable,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErrorCode::InvalidAddress
+            | ErrorCode::InvalidChainId
+            | ErrorCode::InvalidHash
+            | ErrorCode::InvalidParameter
+            | ErrorCode::PaginationOutOfRange => StatusCode::BAD_REQUEST,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::UpstreamUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidAddress => "Invalid address",
+            ErrorCode::InvalidChainId => "Invalid chain id",
+            ErrorCode::InvalidHash => "Invalid hash",
+            ErrorCode::InvalidParameter => "Invalid parameter",
+            ErrorCode::NotFound => "Not Found",
+            ErrorCode::PaginationOutOfRange => "Pagination out of range",
+            ErrorCode::Unauthorized => "Unauthorized",
+            ErrorCode::TooManyRequests => "Too many requests",
+            ErrorCode::UpstreamUnavailable => "Upstream service unavailable",
+            ErrorCode::Internal => "Internal server error",
+        }
+    }
+
+    /// Whether the underlying error may be shown to the client
+    pub fn is_client_error(&self) -> bool {
+        self.status().is_client_error()
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ErrorBody {
+    pub code: ErrorCode,
+    pub message: String,
+    pub details: Option<String>,
+}
+
+/// Body of every error response
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: ErrorBody,
+}
 
 #[derive(Debug)]
-pub struct AppError((Option<StatusCode>, Error));
+pub struct AppError {
+    code: ErrorCode,
+    error: Error,
+}
 
 impl AppError {
-    pub fn new(err: Error) -> Self {
-        Self((None, err))
+    pub fn new<E: Into<Error>>(code: ErrorCode, err: E) -> Self {
+        Self {
+            code,
+            error: err.into(),
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.code
     }
 
-    pub fn status<E: Into<anyhow::Error>>(status: StatusCode, err: E) -> Self {
-        Self((Some(status), err.into()))
+    /// The underlying error, only for errors that may be shown to the client
+    pub fn details(&self) -> Option<String> {
+        self.code
+            .is_client_error()
+            .then(|| format!("{}", self.error))
     }
 
     pub fn not_found() -> Self {
-        Self::status(StatusCode::NOT_FOUND, anyhow!("Not Found"))
+        Self::new(ErrorCode::NotFound, anyhow!("Not Found"))
+    }
+
+    pub fn invalid_address<E: Into<Error>>(err: E) -> Self {
+        Self::new(ErrorCode::InvalidAddress, err)
+    }
+
+    pub fn invalid_chain_id<E: Into<Error>>(err: E) -> Self {
+        Self::new(ErrorCode::InvalidChainId, err)
+    }
+
+    pub fn invalid_hash<E: Into<Error>>(err: E) -> Self {
+        Self::new(ErrorCode::InvalidHash, err)
+    }
+
+    pub fn invalid_parameter<E: Into<Error>>(err: E) -> Self {
+        Self::new(ErrorCode::InvalidParameter, err)
+    }
+
+    pub fn pagination_out_of_range<E: Into<Error>>(err: E) -> Self {
+        Self::new(ErrorCode::PaginationOutOfRange, err)
+    }
+
+    pub fn unauthorized<E: Into<Error>>(err: E) -> Self {
+        Self::new(ErrorCode::Unauthorized, err)
+    }
+
+    pub fn too_many_requests<E: Into<Error>>(err: E) -> Self {
+        Self::new(ErrorCode::TooManyRequests, err)
     }
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        let details = self.details();
+        if !self.code.is_client_error() {
+            error!("{}: {:?}", self.code, self.error);
+        }
         (
-            self.0 .0.unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
-            format!("{}", self.0 .1),
+            self.code.status(),
+            Json(ErrorResponse {
+                error: ErrorBody {
+                    code: self.code,
+                    message: self.code.message().to_string(),
+                    details,
+                },
+            }),
         )
             .into_response()
     }
@@ -36,6 +169,28 @@ where
     E: Into<anyhow::Error>,
 {
     fn from(err: E) -> Self {
-        Self((None, err.into()))
+        let err = err.into();
+        let code = match is_upstream_unavailable(&err) {
+            true => ErrorCode::UpstreamUnavailable,
+            false => ErrorCode::Internal,
+        };
+        Self::new(code, err)
+    }
+}
+
+/// Connection failures to Postgres or Redis, as opposed to errors in a query itself
+fn is_upstream_unavailable(err: &Error) -> bool {
+    if err.downcast_ref::<PoolError>().is_some() {
+        return true;
+    }
+    if let Some(err) = err.downcast_ref::<tokio_postgres::Error>() {
+        return err.is_closed()
+            || err
+                .source()
+                .is_some_and(|source| source.is::<std::io::Error>());
+    }
+    if let Some(err) = err.downcast_ref::<redis::RedisError>() {
+        return err.is_io_error() || err.is_connection_dropped() || err.is_timeout();
     }
+    false
 }
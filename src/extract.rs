@@ -0,0 +1,56 @@
+use anyhow::anyhow;
+use axum::{
+    async_trait,
+    extract::{rejection::PathRejection, rejection::QueryRejection, FromRequestParts},
+    http::request::Parts,
+};
+use serde::de::DeserializeOwned;
+
+use crate::error::AppError;
+
+/// [`axum::extract::Query`], rejecting malformed queries with the JSON error envelope
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Query<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Query(query) = axum::extract::Query::from_request_parts(parts, state)
+            .await
+            .map_err(|rejection: QueryRejection| {
+                AppError::invalid_parameter(anyhow!(rejection.body_text()))
+            })?;
+        Ok(Self(query))
+    }
+}
+
+/// [`axum::extract::Path`], rejecting malformed path parameters with the JSON error envelope
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Path<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Path(path) = axum::extract::Path::from_request_parts(parts, state)
+            .await
+            .map_err(
+                |rejection: PathRejection| match rejection.status().is_client_error() {
+                    true => AppError::invalid_parameter(anyhow!(rejection.body_text())),
+                    false => AppError::from(anyhow!(rejection.body_text())),
+                },
+            )?;
+        Ok(Self(path))
+    }
+}
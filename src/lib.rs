@@ -1,6 +1,8 @@
 pub mod api;
+pub mod chains;
 pub mod config;
 pub mod error;
+pub mod extract;
 pub mod middleware;
 pub mod state;
 pub mod types;
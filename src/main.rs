@@ -1,5 +1,5 @@
 use std::{
-    net::Ipv4Addr,
+    net::{Ipv4Addr, SocketAddr},
     panic::{set_hook, take_hook},
     process::exit,
 };
@@ -7,11 +7,12 @@ use std::{
 use anyhow::{anyhow, Error};
 use axum::{serve, Router};
 use log::{error, info};
+use once_cell::sync::Lazy;
 use tokio::net::TcpListener;
 use tower_http::cors::CorsLayer;
 use tracing::level_filters::LevelFilter;
 use tracing_subscriber::EnvFilter;
-use zkscan_api::{api, config::CONFIG};
+use zkscan_api::{api, chains::CHAINS, config::CONFIG, middleware::API_KEYS};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -40,14 +41,21 @@ async fn main() -> Result<(), Error> {
         .compact()
         .init();
 
+    // a bad registry or API keys file fails the startup rather than the first request reading it
+    Lazy::force(&CHAINS);
+    Lazy::force(&API_KEYS);
+
     let app = Router::new()
         .nest("/api/v1/", api::routes())
         .route_layer(CorsLayer::new().allow_origin(["https://www.evmtrace.info".parse()?]));
     let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, CONFIG.port)).await?;
     info!("Server is listening on http://0.0.0.0:{}", CONFIG.port,);
-    serve(listener, app)
-        .await
-        .map_err(|e| anyhow!("Server error: {}", e))?;
+    serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .map_err(|e| anyhow!("Server error: {}", e))?;
 
     Ok(())
 }
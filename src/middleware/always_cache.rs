@@ -1,64 +1,647 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    marker::PhantomData,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Error;
 use axum::{
-    body::Body,
+    body::Bytes,
     extract::{OriginalUri, Request, State},
+    http::{
+        header::{
+            HeaderName, ACCEPT, CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_LANGUAGE, CONTENT_TYPE,
+            ETAG, IF_NONE_MATCH, LOCATION, VARY,
+        },
+        response::Parts,
+        HeaderMap, HeaderValue, StatusCode,
+    },
     middleware::Next,
     response::{IntoResponse, Response},
-    Json,
 };
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ethers_core::{
+    rand::random,
+    utils::{hex, keccak256},
+};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
 use http_body_util::BodyExt;
-use redis::AsyncCommands;
-use serde_json::{from_str, Value};
+use log::warn;
+use once_cell::sync::Lazy;
+use redis::{aio::ConnectionLike, AsyncCommands, Script};
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str, to_string};
+use tokio::{sync::Mutex as AsyncMutex, time::sleep};
+
+use super::{cache_key, CacheVary};
+use crate::{error::AppError, state::State as AppState, types::ExportFormat};
+
+/// To be used with account, latest and block endpoints
+pub type ShortAlwaysCacheMiddleware<Q> = AlwaysCacheMiddleware<10, 50, Q>;
+/// To be used with tags, stats and other slowly changing aggregates
+pub type LongAlwaysCacheMiddleware<Q> = AlwaysCacheMiddleware<3600, 82800, Q>;
+
+pub type AlwaysCacheWithQueryMiddleware<const CACHE_TTL: u32, const STALE_TTL: u32, Q> =
+    AlwaysCacheMiddleware<CACHE_TTL, STALE_TTL, Q>;
+pub type AlwaysCacheWithoutQueryMiddleware<const CACHE_TTL: u32, const STALE_TTL: u32> =
+    AlwaysCacheMiddleware<CACHE_TTL, STALE_TTL, ()>;
+
+/// Fresh and stale seconds of responses marked [`Immutable`], whatever the route's tier
+const IMMUTABLE_TTL: (u32, u32) = (3600, 82800);
 
-use crate::{error::AppError, state::State as AppState};
+/// Response headers kept in the cache, the others are dropped
+const STORED_HEADERS: [HeaderName; 4] = [
+    CONTENT_TYPE,
+    CONTENT_DISPOSITION,
+    CONTENT_LANGUAGE,
+    LOCATION,
+];
+/// Bodies from this size on are stored deflated
+const COMPRESSION_THRESHOLD: usize = 1024;
 
-/// To be used with account and latest endpoints
-pub type ShortAlwaysCacheMiddleware<const WITH_QUERY: bool> = AlwaysCacheMiddleware<10, WITH_QUERY>;
-/// To be used with block and txs endpoints
-pub type LongAlwaysCacheMiddleware<const WITH_QUERY: bool> =
-    AlwaysCacheMiddleware<3600, WITH_QUERY>;
+/// Seconds another instance may hold the refresh lock of a key before it is considered gone
+const REFRESH_LOCK_TTL: u64 = 30;
+/// How long a miss waits for another instance refreshing the same key before running the handler
+const REFRESH_WAIT: Duration = Duration::from_secs(1);
+const REFRESH_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
-pub type AlwaysCacheWithQueryMiddleware<const CACHE_TTL: u32> =
-    AlwaysCacheMiddleware<CACHE_TTL, true>;
-pub type AlwaysCacheWithoutQueryMiddleware<const CACHE_TTL: u32> =
-    AlwaysCacheMiddleware<CACHE_TTL, false>;
+/// Deletes a lock only when it holds the token it was taken with
+static RELEASE_SCRIPT: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r#"
+        if redis.call("GET", KEYS[1]) == ARGV[1] then
+            return redis.call("DEL", KEYS[1])
+        end
+        return 0
+        "#,
+    )
+});
+
+/// One lock per key being computed in this process, so that concurrent misses run the handler once
+static IN_FLIGHT: Lazy<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>> = Lazy::new(Default::default);
+
+/// Cached response with the time it was stored, so that a stale entry can be told from a fresh one.
+/// The body is kept as sent, so that its `ETag` is the same on every hit.
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    stored_at: u64,
+    status: u16,
+    /// The [`STORED_HEADERS`] the response had
+    headers: Vec<(String, String)>,
+    /// Whether `body` is deflated
+    compressed: bool,
+    /// Whether the response was marked [`Immutable`]
+    #[serde(default)]
+    immutable: bool,
+    /// Base64 of the body bytes
+    body: String,
+}
+
+impl CacheEntry {
+    fn new(parts: &Parts, body: &[u8]) -> Result<Self, Error> {
+        let compressed = body.len() >= COMPRESSION_THRESHOLD;
+        let body = match compressed {
+            true => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+                encoder.write_all(body)?;
+                encoder.finish()?
+            }
+            false => body.to_vec(),
+        };
+        Ok(Self {
+            stored_at: now(),
+            status: parts.status.as_u16(),
+            headers: STORED_HEADERS
+                .iter()
+                .filter_map(|name| {
+                    let value = parts.headers.get(name)?.to_str().ok()?;
+                    Some((name.to_string(), value.to_string()))
+                })
+                .collect(),
+            compressed,
+            immutable: parts.extensions.get::<Immutable>().is_some(),
+            body: STANDARD.encode(body),
+        })
+    }
+
+    /// Seconds since the entry was stored, and how it is served with the given TTLs or the
+    /// [`IMMUTABLE_TTL`]
+    fn freshness(&self, cache_ttl: u32, stale_ttl: u32) -> (u64, Freshness) {
+        let age = now().saturating_sub(self.stored_at);
+        let (cache_ttl, stale_ttl) = match self.immutable {
+            true => IMMUTABLE_TTL,
+            false => (cache_ttl, stale_ttl),
+        };
+        (age, freshness(age, cache_ttl, stale_ttl))
+    }
 
+    fn into_parts(self) -> Result<(StatusCode, HeaderMap, Bytes, bool), Error> {
+        let mut body = STANDARD.decode(&self.body)?;
+        if self.compressed {
+            let mut inflated = Vec::new();
+            DeflateDecoder::new(body.as_slice()).read_to_end(&mut inflated)?;
+            body = inflated;
+        }
+        let headers = self
+            .headers
+            .iter()
+            .map(|(name, value)| Ok((HeaderName::try_from(name)?, HeaderValue::try_from(value)?)))
+            .collect::<Result<HeaderMap, Error>>()?;
+        Ok((
+            StatusCode::from_u16(self.status)?,
+            headers,
+            Bytes::from(body),
+            self.immutable,
+        ))
+    }
+}
+
+/// Response extension keeping a response out of the cache, as in `(Extension(Uncacheable), body)`
+#[derive(Debug, Clone, Copy)]
+pub struct Uncacheable;
+
+/// Response extension for content that never changes anymore, as in `(Extension(Immutable), body)`.
+/// It is cached for [`IMMUTABLE_TTL`] and marked `immutable` in `Cache-Control`.
+#[derive(Debug, Clone, Copy)]
+pub struct Immutable;
+
+/// Responses are fresh for `CACHE_TTL` seconds, then served stale for up to `STALE_TTL` more
+/// seconds while a single task refreshes them in the background. Entries are keyed by
+/// [`cache_key`], on the query parameters `Q` varies on.
+///
+/// Cached responses carry a strong `ETag` and a `Cache-Control` header matching their remaining
+/// freshness, or marking them `immutable` when the handler returned [`Immutable`]. A matching
+/// `If-None-Match` gets a 304.
+/// As exports are negotiated on the same URLs, they all `Vary` on `Accept`.
 #[derive(Copy, Clone)]
-pub struct AlwaysCacheMiddleware<const CACHE_TTL: u32, const WITH_QUERY: bool>;
+pub struct AlwaysCacheMiddleware<const CACHE_TTL: u32, const STALE_TTL: u32, Q>(PhantomData<Q>);
 
-impl<const CACHE_TTL: u32, const WITH_QUERY: bool> AlwaysCacheMiddleware<CACHE_TTL, WITH_QUERY> {
+impl<const CACHE_TTL: u32, const STALE_TTL: u32, Q: CacheVary>
+    AlwaysCacheMiddleware<CACHE_TTL, STALE_TTL, Q>
+{
     pub async fn handler(
         State(state): State<AppState>,
         OriginalUri(uri): OriginalUri,
         request: Request,
         next: Next,
     ) -> Result<Response, AppError> {
+        // exports share the key of their JSON counterpart, `format` is never varied on
+        if !matches!(
+            ExportFormat::negotiate(request.uri(), request.headers()),
+            Ok(ExportFormat::Json)
+        ) {
+            return Ok(next.run(request).await);
+        }
+
+        let if_none_match = request.headers().get(IF_NONE_MATCH).cloned();
         let mut redis = state.redis_pool.aquire().await?;
-        let key = format!(
-            "{}:{}",
-            match WITH_QUERY {
-                true => uri.to_string(),
-                false => uri.path().to_string(),
-            },
-            request.method()
-        );
-        let cached_response = redis.get::<&str, Option<String>>(&key).await?;
-        if let Some(cached_response) = cached_response {
-            return Ok(Json(from_str::<Value>(&cached_response)?).into_response());
+        let key = cache_key::<Q>(request.method(), &uri);
+
+        if let Some(entry) = get_entry(&mut *redis, &key).await? {
+            let (age, freshness) = entry.freshness(CACHE_TTL, STALE_TTL);
+            match freshness {
+                Freshness::Fresh => return Self::from_cache(entry, age, if_none_match.as_ref()),
+                // refreshed by a single task across this process and other instances
+                Freshness::Stale => {
+                    if let Ok(guard) = key_lock(&key).try_lock_owned() {
+                        // when Redis fails, a later request refreshes it instead
+                        if let Ok(Some(token)) = acquire_refresh_lock(&mut *redis, &key).await {
+                            tokio::spawn(async move {
+                                let _guard = guard;
+                                let refreshed = async {
+                                    let mut redis = state.redis_pool.aquire().await?;
+                                    let response = next.run(request).await;
+                                    Self::store(&mut *redis, &key, response, None).await?;
+                                    release_refresh_lock(&mut *redis, &key, &token).await
+                                };
+                                if let Err(e) = refreshed.await {
+                                    warn!("Failed to refresh cache entry {}: {:?}", key, e);
+                                }
+                            });
+                        }
+                    }
+                    return Self::from_cache(entry, age, if_none_match.as_ref());
+                }
+                // only left in Redis by a clock skewed instance, computed again as a miss
+                Freshness::Expired => {}
+            }
+        }
+
+        // miss, the first request runs the handler while the others wait for its result
+        let lock = key_lock(&key);
+        let _guard = lock.lock().await;
+        if let Some((entry, age)) = fresh_entry(&mut *redis, &key, CACHE_TTL).await? {
+            return Self::from_cache(entry, age, if_none_match.as_ref());
+        }
+        let token = acquire_refresh_lock(&mut *redis, &key).await?;
+        if token.is_none() {
+            if let Some((entry, age)) = wait_for_entry(&mut *redis, &key, CACHE_TTL).await? {
+                return Self::from_cache(entry, age, if_none_match.as_ref());
+            }
         }
+
         let response = next.run(request).await;
+        let response = Self::store(&mut *redis, &key, response, if_none_match.as_ref()).await?;
+        if let Some(token) = token {
+            release_refresh_lock(&mut *redis, &key, &token).await.ok();
+        }
+        Ok(response)
+    }
+
+    /// Caches successful responses, returning them as served from the cache.
+    /// Other responses are returned unchanged.
+    async fn store<C: ConnectionLike + Send>(
+        redis: &mut C,
+        key: &str,
+        response: Response,
+        if_none_match: Option<&HeaderValue>,
+    ) -> Result<Response, AppError> {
         let (parts, body) = response.into_parts();
 
-        // check if error, if so, return response as is
-        if parts.status.is_client_error() || parts.status.is_server_error() {
+        // check if error, streamed or opted out, if so, return response as is
+        if !is_cacheable(&parts) {
             return Ok(Response::from_parts(parts, body));
         }
 
         let bytes = body.collect().await?.to_bytes();
-        let Json(body) = Json::<Value>::from_bytes(&bytes)?;
+        let entry = CacheEntry::new(&parts, &bytes)?;
+        let (cache_ttl, stale_ttl) = match entry.immutable {
+            true => IMMUTABLE_TTL,
+            false => (CACHE_TTL, STALE_TTL),
+        };
         redis
-            .set_ex::<&str, String, ()>(&key, serde_json::to_string(&body)?, CACHE_TTL as u64)
+            .set_ex::<&str, String, ()>(
+                key,
+                to_string(&entry)?,
+                cache_ttl as u64 + stale_ttl as u64,
+            )
             .await
             .ok();
-        Ok(Response::from_parts(parts, Body::from(bytes)))
+        Ok(Self::respond(
+            parts.status,
+            parts.headers,
+            bytes,
+            0,
+            entry.immutable,
+            if_none_match,
+        ))
+    }
+
+    fn from_cache(
+        entry: CacheEntry,
+        age: u64,
+        if_none_match: Option<&HeaderValue>,
+    ) -> Result<Response, AppError> {
+        let (status, headers, body, immutable) = entry.into_parts()?;
+        Ok(Self::respond(
+            status,
+            headers,
+            body,
+            age,
+            immutable,
+            if_none_match,
+        ))
+    }
+
+    /// Serves a cached body, or an empty 304 when the client already holds it
+    fn respond(
+        status: StatusCode,
+        headers: HeaderMap,
+        body: Bytes,
+        age: u64,
+        immutable: bool,
+        if_none_match: Option<&HeaderValue>,
+    ) -> Response {
+        let etag = format!("\"{}\"", hex::encode(keccak256(&body)));
+        let cache_control = match immutable {
+            true => format!("public, max-age={}, immutable", IMMUTABLE_TTL.0),
+            false => format!(
+                "public, max-age={}, stale-while-revalidate={}",
+                (CACHE_TTL as u64).saturating_sub(age),
+                STALE_TTL
+            ),
+        };
+        // the same URL serves CSV or NDJSON when asked for in `Accept`
+        let cache_headers = [
+            (ETAG, etag.clone()),
+            (CACHE_CONTROL, cache_control),
+            (VARY, ACCEPT.to_string()),
+        ];
+
+        if if_none_match.is_some_and(|tags| matches_etag(tags, &etag)) {
+            return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+        }
+        (status, headers, cache_headers, body).into_response()
+    }
+}
+
+/// Whether an `If-None-Match` list holds `etag`, compared weakly as the RFC asks for
+fn matches_etag(tags: &HeaderValue, etag: &str) -> bool {
+    tags.to_str().is_ok_and(|tags| {
+        tags.split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+    })
+}
+
+/// How a cached entry is served depending on its age
+#[derive(Debug, PartialEq, Eq)]
+enum Freshness {
+    Fresh,
+    /// Served while being refreshed
+    Stale,
+    Expired,
+}
+
+fn freshness(age: u64, cache_ttl: u32, stale_ttl: u32) -> Freshness {
+    if age < cache_ttl as u64 {
+        Freshness::Fresh
+    } else if age < cache_ttl as u64 + stale_ttl as u64 {
+        Freshness::Stale
+    } else {
+        Freshness::Expired
+    }
+}
+
+fn is_cacheable(parts: &Parts) -> bool {
+    parts.status.is_success()
+        && parts.extensions.get::<Uncacheable>().is_none()
+        && parts
+            .headers
+            .get(CONTENT_TYPE)
+            .is_none_or(|content_type| content_type != "text/event-stream")
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+fn key_lock(key: &str) -> Arc<AsyncMutex<()>> {
+    let mut in_flight = IN_FLIGHT.lock().unwrap_or_else(PoisonError::into_inner);
+    // forget the locks nobody holds anymore
+    in_flight.retain(|_, lock| Arc::strong_count(lock) > 1);
+    in_flight.entry(key.to_string()).or_default().clone()
+}
+
+/// Entries written before `stored_at` existed are treated as misses
+async fn get_entry<C: ConnectionLike + Send>(
+    redis: &mut C,
+    key: &str,
+) -> Result<Option<CacheEntry>, AppError> {
+    let cached = redis.get::<&str, Option<String>>(key).await?;
+    Ok(cached.and_then(|cached| from_str::<CacheEntry>(&cached).ok()))
+}
+
+/// Entry of `key` with its age, unless missing or no longer fresh. A stale or expired entry
+/// still in Redis does not count as computed.
+async fn fresh_entry<C: ConnectionLike + Send>(
+    redis: &mut C,
+    key: &str,
+    cache_ttl: u32,
+) -> Result<Option<(CacheEntry, u64)>, AppError> {
+    Ok(get_entry(redis, key).await?.and_then(|entry| {
+        let (age, freshness) = entry.freshness(cache_ttl, 0);
+        (freshness == Freshness::Fresh).then_some((entry, age))
+    }))
+}
+
+/// Takes the refresh lock of `key`, returning the token to release it with when acquired
+async fn acquire_refresh_lock<C: ConnectionLike + Send>(
+    redis: &mut C,
+    key: &str,
+) -> Result<Option<String>, AppError> {
+    let token = format!("{:032x}", random::<u128>());
+    let acquired = redis::cmd("SET")
+        .arg(format!("lock:{}", key))
+        .arg(&token)
+        .arg("NX")
+        .arg("EX")
+        .arg(REFRESH_LOCK_TTL)
+        .query_async::<_, Option<String>>(redis)
+        .await?;
+    Ok(acquired.map(|_| token))
+}
+
+/// Releases the refresh lock of `key` if still held with `token`, a lock that expired while
+/// refreshing may have been taken by another instance since
+async fn release_refresh_lock<C: ConnectionLike + Send>(
+    redis: &mut C,
+    key: &str,
+    token: &str,
+) -> Result<(), AppError> {
+    RELEASE_SCRIPT
+        .key(format!("lock:{}", key))
+        .arg(token)
+        .invoke_async::<_, ()>(redis)
+        .await?;
+    Ok(())
+}
+
+/// Polls for the fresh entry another instance is computing, giving up after [`REFRESH_WAIT`]
+async fn wait_for_entry<C: ConnectionLike + Send>(
+    redis: &mut C,
+    key: &str,
+    cache_ttl: u32,
+) -> Result<Option<(CacheEntry, u64)>, AppError> {
+    let attempts = REFRESH_WAIT.as_millis() / REFRESH_POLL_INTERVAL.as_millis();
+    for _ in 0..attempts {
+        sleep(REFRESH_POLL_INTERVAL).await;
+        if let Some(entry) = fresh_entry(redis, key, cache_ttl).await? {
+            return Ok(Some(entry));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{from_value, to_value};
+
+    use super::*;
+
+    fn parts() -> Parts {
+        let (parts, _) = Response::builder()
+            .status(StatusCode::CREATED)
+            .header(CONTENT_TYPE, "application/json")
+            .header(CONTENT_DISPOSITION, "inline")
+            .header("x-request-id", "dropped")
+            .body(())
+            .unwrap()
+            .into_parts();
+        parts
+    }
+
+    /// Stores `body` the way Redis would hold it and reads it back
+    fn round_trip(body: &[u8]) -> (CacheEntry, (StatusCode, HeaderMap, Bytes, bool)) {
+        let entry = CacheEntry::new(&parts(), body).unwrap();
+        let stored = from_str::<CacheEntry>(&to_string(&entry).unwrap()).unwrap();
+        (entry, stored.into_parts().unwrap())
+    }
+
+    #[test]
+    fn small_entries_round_trip_uncompressed() {
+        let body = br#"{"data":[]}"#;
+        let (entry, (status, headers, bytes, _)) = round_trip(body);
+        assert!(!entry.compressed);
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers[CONTENT_TYPE], "application/json");
+        assert_eq!(headers[CONTENT_DISPOSITION], "inline");
+        assert_eq!(bytes.as_ref(), body);
+    }
+
+    #[test]
+    fn large_entries_round_trip_compressed() {
+        let body = r#"{"id":1,"hash":"0x00"},"#.repeat(100);
+        let (entry, (status, headers, bytes, _)) = round_trip(body.as_bytes());
+        assert!(entry.compressed);
+        assert!(STANDARD.decode(&entry.body).unwrap().len() < body.len());
+        assert_eq!(status, StatusCode::CREATED);
+        assert!(headers.get("x-request-id").is_none());
+        assert_eq!(bytes.as_ref(), body.as_bytes());
+    }
+
+    #[test]
+    fn etags_match_if_none_match_lists() {
+        let etag = "\"abc\"";
+        for tags in [
+            "\"abc\"",
+            "*",
+            "\"xyz\", \"abc\"",
+            "W/\"abc\"",
+            "\"xyz\",W/\"abc\"",
+        ] {
+            assert!(
+                matches_etag(&HeaderValue::from_static(tags), etag),
+                "{} should match",
+                tags
+            );
+        }
+        for tags in ["\"xyz\"", "abc", "\"abc\"x", ""] {
+            assert!(
+                !matches_etag(&HeaderValue::from_static(tags), etag),
+                "{} should not match",
+                tags
+            );
+        }
+    }
+
+    #[test]
+    fn entries_age_from_fresh_to_expired() {
+        assert_eq!(freshness(0, 10, 50), Freshness::Fresh);
+        assert_eq!(freshness(9, 10, 50), Freshness::Fresh);
+        assert_eq!(freshness(10, 10, 50), Freshness::Stale);
+        assert_eq!(freshness(59, 10, 50), Freshness::Stale);
+        assert_eq!(freshness(60, 10, 50), Freshness::Expired);
+        assert_eq!(freshness(10, 10, 0), Freshness::Expired);
+    }
+
+    /// Answers every command with the entry stored `age` seconds ago, as a `GET` would
+    struct StoredEntry {
+        age: u64,
+    }
+
+    impl ConnectionLike for StoredEntry {
+        fn req_packed_command<'a>(
+            &'a mut self,
+            _cmd: &'a redis::Cmd,
+        ) -> redis::RedisFuture<'a, redis::Value> {
+            let mut entry = CacheEntry::new(&parts(), b"{}").unwrap();
+            entry.stored_at -= self.age;
+            let value = redis::Value::Data(to_string(&entry).unwrap().into_bytes());
+            Box::pin(async move { Ok(value) })
+        }
+
+        fn req_packed_commands<'a>(
+            &'a mut self,
+            _cmd: &'a redis::Pipeline,
+            _offset: usize,
+            _count: usize,
+        ) -> redis::RedisFuture<'a, Vec<redis::Value>> {
+            unimplemented!()
+        }
+
+        fn get_db(&self) -> i64 {
+            0
+        }
+    }
+
+    #[tokio::test]
+    async fn only_fresh_entries_are_served_after_a_miss() {
+        let entry = fresh_entry(&mut StoredEntry { age: 4 }, "key", 10).await;
+        let (_, age) = entry.unwrap().expect("fresh entry");
+        assert!((4..6).contains(&age));
+
+        for age in [10, 100] {
+            let entry = fresh_entry(&mut StoredEntry { age }, "key", 10).await;
+            assert!(entry.unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn immutable_entries_outlive_the_route_tier() {
+        let (mut parts, _) = Response::new(()).into_parts();
+        parts.extensions.insert(Immutable);
+        let mut entry = CacheEntry::new(&parts, b"{}").unwrap();
+        assert!(entry.immutable);
+        entry.stored_at -= 600;
+        assert_eq!(entry.freshness(10, 50).1, Freshness::Fresh);
+
+        // entries stored before the flag existed are not immutable
+        let mut stored = to_value(&entry).unwrap();
+        stored.as_object_mut().unwrap().remove("immutable");
+        let entry = from_value::<CacheEntry>(stored).unwrap();
+        assert_eq!(entry.freshness(10, 50).1, Freshness::Expired);
+
+        type Cache = ShortAlwaysCacheMiddleware<()>;
+        let response = Cache::respond(
+            StatusCode::OK,
+            HeaderMap::new(),
+            Bytes::new(),
+            600,
+            true,
+            None,
+        );
+        assert_eq!(
+            response.headers()[CACHE_CONTROL],
+            "public, max-age=3600, immutable"
+        );
+    }
+
+    #[test]
+    fn matching_etag_gets_not_modified() {
+        type Cache = ShortAlwaysCacheMiddleware<()>;
+        let body = Bytes::from_static(b"{}");
+        let response = Cache::respond(
+            StatusCode::OK,
+            HeaderMap::new(),
+            body.clone(),
+            4,
+            false,
+            None,
+        );
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[CACHE_CONTROL],
+            "public, max-age=6, stale-while-revalidate=50"
+        );
+
+        let etag = response.headers()[ETAG].clone();
+        let response = Cache::respond(
+            StatusCode::OK,
+            HeaderMap::new(),
+            body,
+            4,
+            false,
+            Some(&etag),
+        );
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[ETAG], etag);
+        assert_eq!(response.headers()[VARY], "accept");
     }
 }
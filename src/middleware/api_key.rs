@@ -0,0 +1,126 @@
+use std::{collections::HashMap, fs::read_to_string, str::FromStr};
+
+use anyhow::{anyhow, Error, Result};
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::{request::Parts, HeaderMap, Uri},
+};
+use log::info;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::from_str;
+
+use crate::{
+    config::{RateLimit, CONFIG},
+    error::AppError,
+};
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Known API keys and their tier, from `API_KEYS_FILE` and `API_KEYS`
+pub static API_KEYS: Lazy<HashMap<String, ApiTier>> =
+    Lazy::new(|| load_api_keys().expect("Failed to load API keys"));
+
+fn load_api_keys() -> Result<HashMap<String, ApiTier>> {
+    let mut keys = match &CONFIG.api_keys_file {
+        Some(path) => {
+            info!("Loading API keys from {}", path);
+            from_str::<HashMap<String, ApiTier>>(&read_to_string(path)?)?
+        }
+        None => HashMap::new(),
+    };
+    for entry in &CONFIG.api_keys {
+        let (key, tier) = match entry.split_once(':') {
+            Some((key, tier)) => (key, tier.parse()?),
+            None => (entry.as_str(), ApiTier::default()),
+        };
+        keys.insert(key.to_string(), tier);
+    }
+    Ok(keys)
+}
+
+/// Quota class of a caller, see `RATE_LIMIT_*`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiTier {
+    /// Requests without an API key
+    Anonymous,
+    #[default]
+    Standard,
+    Partner,
+}
+
+impl ApiTier {
+    /// Cost units allowed per rate limit window
+    pub fn quota(&self) -> u32 {
+        self.quota_in(&CONFIG.rate_limit)
+    }
+
+    /// Cost units allowed per window under `limits`
+    pub fn quota_in(&self, limits: &RateLimit) -> u32 {
+        match self {
+            ApiTier::Anonymous => limits.anonymous,
+            ApiTier::Standard => limits.standard,
+            ApiTier::Partner => limits.partner,
+        }
+    }
+}
+
+impl FromStr for ApiTier {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "anonymous" => Ok(ApiTier::Anonymous),
+            "standard" => Ok(ApiTier::Standard),
+            "partner" => Ok(ApiTier::Partner),
+            _ => Err(anyhow!("Unknown API tier {}", s)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ApiKeyQuery {
+    pub api_key: Option<String>,
+}
+
+/// Key of an authenticated request, read from the `X-API-Key` header or else `?api_key=`.
+/// Rejects requests without a key or with an unknown key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ApiKey {
+    pub key: String,
+    pub tier: ApiTier,
+}
+
+impl ApiKey {
+    /// The key sent with a request, if any. A key that is sent but unknown is rejected
+    /// rather than treated as anonymous, so that a typo does not silently lower the quota.
+    pub fn resolve(uri: &Uri, headers: &HeaderMap) -> Result<Option<Self>, AppError> {
+        let key = match headers.get(API_KEY_HEADER) {
+            Some(key) => Some(key.to_str().map_err(AppError::unauthorized)?.to_string()),
+            None => {
+                let Query(query) =
+                    Query::<ApiKeyQuery>::try_from_uri(uri).map_err(AppError::invalid_parameter)?;
+                query.api_key
+            }
+        };
+        let Some(key) = key else {
+            return Ok(None);
+        };
+        let tier = *API_KEYS
+            .get(&key)
+            .ok_or_else(|| AppError::unauthorized(anyhow!("Invalid API key")))?;
+        Ok(Some(Self { key, tier }))
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ApiKey {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Self::resolve(&parts.uri, &parts.headers)?
+            .ok_or_else(|| AppError::unauthorized(anyhow!("Missing API key")))
+    }
+}
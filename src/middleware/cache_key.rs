@@ -0,0 +1,178 @@
+use std::borrow::Cow;
+
+use axum::http::{Method, Uri};
+
+/// Bump whenever the shape of a cached response changes, so that a deploy never serves entries
+/// of the previous shape
+pub const CACHE_SCHEMA_VERSION: u32 = 1;
+
+/// Query parameters the cached responses of a route vary on, all others are left out of the cache key
+pub trait CacheVary {
+    fn varies_on(param: &str) -> bool;
+}
+
+/// Routes whose responses only depend on the path
+impl CacheVary for () {
+    fn varies_on(_param: &str) -> bool {
+        false
+    }
+}
+
+impl<A: CacheVary, B: CacheVary> CacheVary for (A, B) {
+    fn varies_on(param: &str) -> bool {
+        A::varies_on(param) || B::varies_on(param)
+    }
+}
+
+impl<A: CacheVary, B: CacheVary, C: CacheVary> CacheVary for (A, B, C) {
+    fn varies_on(param: &str) -> bool {
+        A::varies_on(param) || B::varies_on(param) || C::varies_on(param)
+    }
+}
+
+/// Cache key of a request: the schema version, the method, the path and the parameters `Q`
+/// varies on, sorted. Addresses and hashes are lowercased and prefixed with `0x`, so that any
+/// spelling hits the same entry.
+pub fn cache_key<Q: CacheVary>(method: &Method, uri: &Uri) -> String {
+    let path = uri
+        .path()
+        .split('/')
+        .map(normalize)
+        .collect::<Vec<_>>()
+        .join("/");
+    let mut params = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Q::varies_on(name).then(|| format!("{}={}", name, normalize(value)))
+        })
+        .collect::<Vec<_>>();
+    params.sort();
+
+    format!(
+        "v{}:{}:{}?{}",
+        CACHE_SCHEMA_VERSION,
+        method,
+        path,
+        params.join("&")
+    )
+}
+
+/// Lowercases hex addresses and hashes and prefixes them with `0x`, as handlers accept them in any
+/// case and with or without the prefix. The items of comma separated lists are normalized one by one.
+fn normalize(value: &str) -> Cow<'_, str> {
+    if value.contains(',') {
+        return Cow::Owned(
+            value
+                .split(',')
+                .map(normalize)
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+    }
+    let digits = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+    match matches!(digits.len(), 40 | 64) && digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        true => Cow::Owned(format!("0x{}", digits.to_ascii_lowercase())),
+        false => Cow::Borrowed(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    const HASH: &str = "0xB5C8BD9430B6CC87A0E2FE110ECE6BF527FA4F170A4BC8CD032F768FC5219838";
+
+    struct Params;
+
+    impl CacheVary for Params {
+        fn varies_on(param: &str) -> bool {
+            matches!(param, "size" | "address")
+        }
+    }
+
+    fn key(uri: &str) -> String {
+        cache_key::<Params>(&Method::GET, &uri.parse().unwrap())
+    }
+
+    #[test]
+    fn keys_hold_the_version_method_and_path() {
+        assert_eq!(
+            key("/chains"),
+            format!("v{}:GET:/chains?", CACHE_SCHEMA_VERSION)
+        );
+    }
+
+    #[test]
+    fn params_are_sorted() {
+        assert_eq!(
+            key("/tag/a?size=10&address=0x1"),
+            key("/tag/a?address=0x1&size=10")
+        );
+        assert!(key("/tag/a?size=10&address=0x1").ends_with("?address=0x1&size=10"));
+    }
+
+    #[test]
+    fn other_params_are_left_out() {
+        assert_eq!(
+            key("/tag/a?size=10&utm_source=x&_=1"),
+            key("/tag/a?size=10")
+        );
+        assert_ne!(key("/tag/a?size=10"), key("/tag/a?size=20"));
+    }
+
+    #[test]
+    fn hex_values_are_lowercased() {
+        assert_eq!(
+            key(&format!("/address/{}", ADDRESS)),
+            key(&format!("/address/{}", ADDRESS.to_lowercase()))
+        );
+        assert_eq!(
+            key(&format!("/x?address={}", ADDRESS)),
+            key(&format!("/x?address={}", ADDRESS.to_lowercase()))
+        );
+        assert_eq!(
+            key(&format!("/tx/{}", HASH)),
+            key(&format!("/tx/{}", HASH.to_lowercase()))
+        );
+    }
+
+    #[test]
+    fn hex_values_are_prefixed() {
+        assert_eq!(
+            key(&format!("/tx/{}", &HASH[2..])),
+            key(&format!("/tx/{}", HASH.to_lowercase()))
+        );
+        assert_eq!(
+            key(&format!("/x?address={}", &ADDRESS[2..])),
+            key(&format!("/x?address={}", ADDRESS))
+        );
+        assert_eq!(
+            key(&format!("/tx/0X{}", &HASH[2..])),
+            key(&format!("/tx/{}", HASH))
+        );
+        assert!(key(&format!("/tx/{}", &HASH[2..])).contains(&HASH.to_lowercase()));
+    }
+
+    #[test]
+    fn list_items_are_lowercased() {
+        let list = format!("{},{}", ADDRESS, &ADDRESS[2..]);
+        let normalized = format!("{},{}", ADDRESS, ADDRESS).to_lowercase();
+        assert_eq!(
+            key(&format!("/tag/{}", list)),
+            key(&format!("/tag/{}", normalized))
+        );
+    }
+
+    #[test]
+    fn other_values_keep_their_case() {
+        assert_ne!(key("/tag/Bridge"), key("/tag/bridge"));
+        assert_ne!(key("/tx/0xABC"), key("/tx/0xabc"));
+    }
+}
@@ -0,0 +1,73 @@
+use axum::{
+    body::Body,
+    extract::Request,
+    http::header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG},
+    middleware::Next,
+    response::Response,
+};
+use http_body_util::BodyExt;
+use serde::Deserialize;
+use serde_json::{from_slice, to_value, to_vec, Value};
+
+use crate::{chains::CHAINS, error::AppError, extract::Query};
+
+#[derive(Deserialize)]
+pub struct ChainMetadataQuery {
+    pub with_chain: Option<bool>,
+}
+
+/// Embeds a `chain` object next to every `chain_id` of a JSON response when `?with_chain=true`.
+/// Runs outside of the cache so cached bodies stay free of chain metadata.
+#[derive(Copy, Clone)]
+pub struct ChainMetadataMiddleware;
+
+impl ChainMetadataMiddleware {
+    pub async fn handler(
+        Query(query): Query<ChainMetadataQuery>,
+        request: Request,
+        next: Next,
+    ) -> Result<Response, AppError> {
+        let response = next.run(request).await;
+        let is_json = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/json"));
+        if !query.with_chain.unwrap_or_default() || !is_json || !response.status().is_success() {
+            return Ok(response);
+        }
+
+        let (mut parts, body) = response.into_parts();
+        let bytes = body.collect().await?.to_bytes();
+        let mut body = from_slice::<Value>(&bytes)?;
+        embed_chain(&mut body)?;
+        parts.headers.remove(CONTENT_LENGTH);
+        // the ETag is of the body without chain metadata
+        parts.headers.remove(ETAG);
+        Ok(Response::from_parts(parts, Body::from(to_vec(&body)?)))
+    }
+}
+
+fn embed_chain(value: &mut Value) -> Result<(), AppError> {
+    match value {
+        Value::Object(object) => {
+            for value in object.values_mut() {
+                embed_chain(value)?;
+            }
+            let chain = object
+                .get("chain_id")
+                .and_then(Value::as_i64)
+                .and_then(|chain_id| CHAINS.get(chain_id));
+            if let Some(chain) = chain {
+                object.entry("chain").or_insert(to_value(chain)?);
+            }
+        }
+        Value::Array(array) => {
+            for value in array {
+                embed_chain(value)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
@@ -1,2 +1,10 @@
 mod always_cache;
+mod api_key;
+mod cache_key;
+mod chain_metadata;
+mod rate_limit;
 pub use always_cache::*;
+pub use api_key::*;
+pub use cache_key::*;
+pub use chain_metadata::*;
+pub use rate_limit::*;
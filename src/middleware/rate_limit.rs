@@ -0,0 +1,209 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{
+        header::{HeaderName, RETRY_AFTER},
+        HeaderMap, HeaderValue,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use super::{ApiKey, ApiTier};
+use crate::{config::CONFIG, error::AppError, state::State as AppState};
+
+/// Lookups by key: transactions, blocks, tags, chains and the latest feeds
+pub type LightRateLimitMiddleware = RateLimitMiddleware<1>;
+/// Queries scanning many rows: address history, stats and search
+pub type HeavyRateLimitMiddleware = RateLimitMiddleware<5>;
+/// Bulk exports
+pub type ExportRateLimitMiddleware = RateLimitMiddleware<100>;
+
+const LIMIT_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+const REMAINING_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+const RESET_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+
+/// Counts `COST` units per request against the caller's quota in Redis, over fixed windows of
+/// `RATE_LIMIT_WINDOW` seconds. Callers are API keys, or client IPs for requests without a key.
+#[derive(Copy, Clone)]
+pub struct RateLimitMiddleware<const COST: u32>;
+
+impl<const COST: u32> RateLimitMiddleware<COST> {
+    /// Cost units counted per request
+    pub const COST: u32 = COST;
+
+    pub async fn handler(
+        State(state): State<AppState>,
+        request: Request,
+        next: Next,
+    ) -> Result<Response, AppError> {
+        let (caller, tier) = match ApiKey::resolve(request.uri(), request.headers())? {
+            Some(api_key) => (format!("key:{}", api_key.key), api_key.tier),
+            None => (
+                format!(
+                    "ip:{}",
+                    client_ip(&request)
+                        .map(|ip| ip.to_string())
+                        .unwrap_or_default()
+                ),
+                ApiTier::Anonymous,
+            ),
+        };
+
+        let window = CONFIG.rate_limit.window.max(1);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let (window_start, reset) = window_bounds(now, window);
+        let key = format!("ratelimit:{}:{}", caller, window_start);
+
+        let mut redis = state.redis_pool.aquire().await?;
+        let (used,) = redis::pipe()
+            .atomic()
+            .incr(&key, Self::COST)
+            .expire(&key, window as i64)
+            .ignore()
+            .query_async::<_, (u32,)>(&mut *redis)
+            .await?;
+
+        let limit = tier.quota();
+        let Some(remaining) = remaining(limit, used) else {
+            let mut response = AppError::too_many_requests(anyhow!(
+                "Rate limit of {} per {} seconds exceeded",
+                limit,
+                window
+            ))
+            .into_response();
+            let headers = response.headers_mut();
+            set_headers(headers, limit, 0, reset);
+            headers.insert(RETRY_AFTER, HeaderValue::from(reset));
+            return Ok(response);
+        };
+
+        let mut response = next.run(request).await;
+        set_headers(response.headers_mut(), limit, remaining, reset);
+        Ok(response)
+    }
+}
+
+fn set_headers(headers: &mut HeaderMap, limit: u32, remaining: u32, reset: u64) {
+    headers.insert(LIMIT_HEADER, HeaderValue::from(limit));
+    headers.insert(REMAINING_HEADER, HeaderValue::from(remaining));
+    headers.insert(RESET_HEADER, HeaderValue::from(reset));
+}
+
+/// Cost units left in a window once its counter reached `used`, `None` when over `limit`
+fn remaining(limit: u32, used: u32) -> Option<u32> {
+    limit.checked_sub(used)
+}
+
+/// Start of the fixed window `now` falls in, and seconds until the next one
+fn window_bounds(now: u64, window: u64) -> (u64, u64) {
+    let start = now - now % window;
+    (start, start + window - now)
+}
+
+/// `X-Forwarded-For` entry added by the outermost of `hops` trusted proxies. Entries left of it
+/// are set by the client and can't be trusted.
+fn forwarded_ip(value: &str, hops: usize) -> Option<IpAddr> {
+    value
+        .rsplit(',')
+        .nth(hops.checked_sub(1)?)
+        .and_then(|ip| ip.trim().parse().ok())
+}
+
+/// Client IP from `X-Forwarded-For` when trusted, else the peer address of the connection
+fn client_ip(request: &Request) -> Option<IpAddr> {
+    let forwarded = CONFIG
+        .trust_forwarded_for
+        .then(|| request.headers().get("x-forwarded-for"))
+        .flatten()
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| forwarded_ip(value, CONFIG.trusted_proxy_hops));
+    forwarded.or_else(|| {
+        request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RateLimit;
+
+    #[test]
+    fn forwarded_ip_skips_client_entries() {
+        let value = "6.6.6.6, 203.0.113.7, 10.0.0.2";
+        assert_eq!(forwarded_ip(value, 1), "10.0.0.2".parse().ok());
+        assert_eq!(forwarded_ip(value, 2), "203.0.113.7".parse().ok());
+        assert_eq!(forwarded_ip("203.0.113.7", 1), "203.0.113.7".parse().ok());
+        assert_eq!(forwarded_ip("2001:db8::1", 1), "2001:db8::1".parse().ok());
+    }
+
+    #[test]
+    fn forwarded_ip_needs_every_trusted_hop() {
+        assert_eq!(forwarded_ip("203.0.113.7", 2), None);
+        assert_eq!(forwarded_ip("203.0.113.7", 0), None);
+        assert_eq!(forwarded_ip("6.6.6.6, garbage", 1), None);
+        assert_eq!(forwarded_ip("", 1), None);
+    }
+
+    #[test]
+    fn windows_are_aligned() {
+        assert_eq!(window_bounds(120, 60), (120, 60));
+        assert_eq!(window_bounds(179, 60), (120, 1));
+        assert_eq!(window_bounds(1_700_000_001, 3600), (1_699_999_200, 2799));
+        assert_eq!(window_bounds(7, 1), (7, 1));
+    }
+
+    /// Requests of `cost` a caller of `tier` gets per window, counted as the middleware does
+    fn allowed(cost: u32, tier: ApiTier) -> usize {
+        let limits = RateLimit {
+            window: 60,
+            anonymous: 120,
+            standard: 1200,
+            partner: 12000,
+        };
+        let limit = tier.quota_in(&limits);
+        (1..)
+            .map(|request| request * cost)
+            .map_while(|used| remaining(limit, used))
+            .count()
+    }
+
+    #[test]
+    fn quota_is_spent_by_cost() {
+        assert_eq!(
+            allowed(LightRateLimitMiddleware::COST, ApiTier::Anonymous),
+            120
+        );
+        assert_eq!(
+            allowed(HeavyRateLimitMiddleware::COST, ApiTier::Anonymous),
+            24
+        );
+        assert_eq!(
+            allowed(ExportRateLimitMiddleware::COST, ApiTier::Anonymous),
+            1
+        );
+        assert_eq!(
+            allowed(HeavyRateLimitMiddleware::COST, ApiTier::Standard),
+            240
+        );
+        assert_eq!(
+            allowed(ExportRateLimitMiddleware::COST, ApiTier::Partner),
+            120
+        );
+    }
+
+    #[test]
+    fn last_unit_is_spendable() {
+        assert_eq!(remaining(120, 119), Some(1));
+        assert_eq!(remaining(120, 120), Some(0));
+        assert_eq!(remaining(120, 121), None);
+    }
+}
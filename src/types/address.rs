@@ -0,0 +1,215 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error};
+use ethers_core::{types::Address, utils::to_checksum};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+use utoipa::{IntoParams, ToSchema};
+
+use super::{ExportRecord, ProxyDestination, TransactionSummary};
+use crate::{error::AppError, middleware::CacheVary};
+
+/// Checksummed form of `address`, the form addresses are stored in
+pub fn checksum_address(address: &str) -> Result<String, AppError> {
+    Address::from_str(address)
+        .map(|address| to_checksum(&address, None))
+        .map_err(AppError::invalid_address)
+}
+
+/// Checks an inclusive `from_block..=to_block` filter is not empty
+pub fn validate_block_range(
+    from_block: Option<i64>,
+    to_block: Option<i64>,
+) -> Result<(), AppError> {
+    if let (Some(from_block), Some(to_block)) = (from_block, to_block) {
+        if from_block > to_block {
+            return Err(AppError::invalid_parameter(anyhow!(
+                "from_block must not be greater than to_block"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Part an address plays in a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressRole {
+    From,
+    To,
+    Ecrecover,
+    Closest,
+}
+
+impl AddressRole {
+    /// Every role `address` plays in `tx`
+    pub fn of(address: &str, tx: &TransactionSummary) -> Vec<Self> {
+        [
+            (AddressRole::From, tx.from_address == address),
+            (AddressRole::To, tx.to_address == address),
+            (
+                AddressRole::Ecrecover,
+                tx.ec_recover_addresses.iter().any(|a| a == address),
+            ),
+            (
+                AddressRole::Closest,
+                tx.closest_address.iter().any(|a| a == address),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(role, matches)| matches.then_some(role))
+        .collect()
+    }
+
+    /// Condition on `transactions` matching `$1` in this role
+    pub fn condition(&self) -> &'static str {
+        match self {
+            AddressRole::From => "transactions.from_address = $1",
+            AddressRole::To => "transactions.to_address = $1",
+            AddressRole::Ecrecover => "ARRAY[$1]::VARCHAR[] <@ transactions.ec_recover_addresses",
+            AddressRole::Closest => "ARRAY[$1]::VARCHAR[] <@ transactions.closest_address",
+        }
+    }
+
+    /// Condition on `transactions` matching `$1` in any role, or only in `role` when given
+    pub fn conditions(role: Option<Self>) -> String {
+        match role {
+            Some(role) => role.condition().to_string(),
+            None => [
+                AddressRole::From,
+                AddressRole::To,
+                AddressRole::Ecrecover,
+                AddressRole::Closest,
+            ]
+            .iter()
+            .map(AddressRole::condition)
+            .collect::<Vec<_>>()
+            .join(" OR "),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TxStatus {
+    Success,
+    Failed,
+}
+
+/// Filters on the transaction history of an address
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
+pub struct AddressFilter {
+    /// Only transactions where the address plays this role
+    pub role: Option<AddressRole>,
+    pub chain_id: Option<i64>,
+    /// Inclusive lower bound on the block number
+    pub from_block: Option<i64>,
+    /// Inclusive upper bound on the block number
+    pub to_block: Option<i64>,
+    pub status: Option<TxStatus>,
+    pub function_signature: Option<String>,
+}
+
+impl CacheVary for AddressFilter {
+    fn varies_on(param: &str) -> bool {
+        matches!(
+            param,
+            "role" | "chain_id" | "from_block" | "to_block" | "status" | "function_signature"
+        )
+    }
+}
+
+impl AddressFilter {
+    pub fn validate(&self) -> Result<(), AppError> {
+        validate_block_range(self.from_block, self.to_block)
+    }
+
+    /// `Some(true)` for successful transactions only, `Some(false)` for failed ones only
+    pub fn is_success(&self) -> Option<bool> {
+        self.status.map(|status| status == TxStatus::Success)
+    }
+}
+
+/// A transaction in the history of an address, with the roles the address played in it
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AddressTransaction {
+    #[serde(flatten)]
+    pub transaction: TransactionSummary,
+    pub roles: Vec<AddressRole>,
+}
+
+impl ExportRecord for AddressTransaction {
+    const HEADERS: &'static [&'static str] = &[
+        "chain_id",
+        "block_number",
+        "block_timestamp",
+        "transaction_hash",
+        "transaction_index",
+        "from_address",
+        "to_address",
+        "value",
+        "error",
+        "function_signature",
+        "function_name",
+        "ec_pairing_count",
+        "ec_recover_addresses",
+        "closest_address",
+        "gas_used_total",
+        "gas_used_first_degree",
+        "roles",
+    ];
+}
+
+/// Number of transactions an address took part in, per role
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RoleCounts {
+    pub from: i64,
+    pub to: i64,
+    pub ecrecover: i64,
+    pub closest: i64,
+}
+
+/// Activity of an address on a single chain
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ChainActivity {
+    pub chain_id: i64,
+    pub transaction_count: i64,
+    pub first_block: i64,
+    pub last_block: i64,
+    pub first_timestamp: Option<i64>,
+    pub last_timestamp: Option<i64>,
+}
+
+impl TryFrom<&Row> for ChainActivity {
+    type Error = Error;
+
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        Ok(Self {
+            chain_id: row.try_get("chain_id")?,
+            transaction_count: row.try_get("transaction_count")?,
+            first_block: row.try_get("first_block")?,
+            last_block: row.try_get("last_block")?,
+            first_timestamp: row.try_get("first_timestamp")?,
+            last_timestamp: row.try_get("last_timestamp")?,
+        })
+    }
+}
+
+/// Aggregates over every transaction an address took part in
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AddressSummary {
+    pub address: String,
+    pub transaction_count: i64,
+    pub role_counts: RoleCounts,
+    pub chains: Vec<ChainActivity>,
+    pub first_seen_timestamp: Option<i64>,
+    pub last_seen_timestamp: Option<i64>,
+    pub failed_transaction_count: i64,
+    pub ec_pairing_count: i64,
+    /// Number of distinct signers recovered with ecrecover in the transactions
+    pub ec_recover_signer_count: i64,
+    pub gas_used_total: i64,
+    pub tags: Vec<String>,
+    pub proxy_destinations: Vec<ProxyDestination>,
+}
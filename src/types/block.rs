@@ -0,0 +1,84 @@
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+use utoipa::ToSchema;
+
+/// A block with its full header, as returned by `/block/:chain-id/:block-number`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Block {
+    pub chain_id: i64,
+    pub number: i64,
+    pub timestamp: i64,
+    pub hash: String,
+    pub parent_hash: String,
+    pub transaction_count: i32,
+    pub nonce: String,
+    pub miner: String,
+    pub difficulty: i64,
+    pub total_difficulty: f64,
+    pub size: i32,
+    pub gas_limit: i64,
+    pub gas_used: i64,
+    pub base_fee_per_gas: i64,
+}
+
+impl Block {
+    pub const COLUMNS: &'static str = "chain_id, number, timestamp, hash, parent_hash, transaction_count, nonce, miner, difficulty, total_difficulty, size, gas_limit, gas_used, base_fee_per_gas";
+}
+
+impl TryFrom<&Row> for Block {
+    type Error = Error;
+
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        Ok(Self {
+            chain_id: row.try_get("chain_id")?,
+            number: row.try_get("number")?,
+            timestamp: row.try_get("timestamp")?,
+            hash: row.try_get("hash")?,
+            parent_hash: row.try_get("parent_hash")?,
+            transaction_count: row.try_get("transaction_count")?,
+            nonce: row.try_get("nonce")?,
+            miner: row.try_get("miner")?,
+            difficulty: row.try_get("difficulty")?,
+            total_difficulty: row.try_get("total_difficulty")?,
+            size: row.try_get("size")?,
+            gas_limit: row.try_get("gas_limit")?,
+            gas_used: row.try_get("gas_used")?,
+            base_fee_per_gas: row.try_get("base_fee_per_gas")?,
+        })
+    }
+}
+
+/// A block as it appears in the latest feed, with the number of indexed transactions it holds
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BlockSummary {
+    /// Row id, used as the id of live feed events
+    #[serde(skip)]
+    pub id: i64,
+    pub chain_id: i64,
+    pub number: i64,
+    pub timestamp: i64,
+    pub hash: String,
+    pub transaction_count: i32,
+    pub related_transaction_count: i64,
+    pub gas_limit: i64,
+    pub gas_used: i64,
+}
+
+impl TryFrom<&Row> for BlockSummary {
+    type Error = Error;
+
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            chain_id: row.try_get("chain_id")?,
+            number: row.try_get("number")?,
+            timestamp: row.try_get("timestamp")?,
+            hash: row.try_get("hash")?,
+            transaction_count: row.try_get("transaction_count")?,
+            related_transaction_count: row.try_get("related_transaction_count")?,
+            gas_limit: row.try_get("gas_limit")?,
+            gas_used: row.try_get("gas_used")?,
+        })
+    }
+}
@@ -0,0 +1,209 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error};
+use ethers_core::{
+    abi::{Abi, Function, HumanReadableParser, Token},
+    types::I256,
+    utils::{hex, to_checksum},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+use utoipa::ToSchema;
+
+/// A single decoded argument of a function call
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DecodedParam {
+    /// Parameter name, only known when decoded with an uploaded ABI
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[schema(value_type = Object)]
+    pub value: Value,
+}
+
+/// Transaction calldata decoded into named, typed arguments
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DecodedInput {
+    /// Canonical signature of the called function, e.g. `verifyProof(uint256[8],uint256[4])`
+    pub signature: String,
+    pub params: Vec<DecodedParam>,
+}
+
+impl DecodedInput {
+    /// Decodes `input` with a text signature such as the ones in `sig_names`
+    pub fn from_signature(signature: &str, input: &str) -> Result<Self, Error> {
+        let function = HumanReadableParser::parse_function(signature)
+            .map_err(|e| anyhow!("Failed to parse signature {}: {}", signature, e))?;
+        Self::decode(&function, &decode_hex(input)?)
+    }
+
+    /// Decodes `input` with the function of `abi` matching its selector
+    pub fn from_abi(abi: &Abi, input: &str) -> Result<Self, Error> {
+        let input = decode_hex(input)?;
+        let selector = input
+            .get(..4)
+            .ok_or_else(|| anyhow!("Input is shorter than a selector"))?;
+        let function = abi
+            .functions()
+            .find(|function| function.short_signature() == selector)
+            .ok_or_else(|| anyhow!("No function in the ABI matches the selector"))?;
+        Self::decode(function, &input)
+    }
+
+    fn decode(function: &Function, input: &[u8]) -> Result<Self, Error> {
+        if input.get(..4) != Some(function.short_signature().as_slice()) {
+            return Err(anyhow!("Selector does not match {}", function.signature()));
+        }
+        let tokens = function.decode_input(&input[4..])?;
+        Ok(Self {
+            // `Function::signature` also lists the outputs when the ABI declares them
+            signature: format!(
+                "{}({})",
+                function.name,
+                function
+                    .inputs
+                    .iter()
+                    .map(|param| param.kind.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            params: function
+                .inputs
+                .iter()
+                .zip(tokens.iter())
+                .map(|(param, token)| {
+                    Ok(DecodedParam {
+                        name: Some(param.name.clone()).filter(|name| !name.is_empty()),
+                        kind: param.kind.to_string(),
+                        value: token_to_value(token)?,
+                    })
+                })
+                .collect::<Result<Vec<_>, Error>>()?,
+        })
+    }
+}
+
+fn decode_hex(input: &str) -> Result<Vec<u8>, Error> {
+    Ok(hex::decode(input.trim_start_matches("0x"))?)
+}
+
+fn token_to_value(token: &Token) -> Result<Value, Error> {
+    Ok(match token {
+        Token::Address(address) => Value::String(to_checksum(address, None)),
+        Token::FixedBytes(bytes) | Token::Bytes(bytes) => {
+            Value::String(format!("0x{}", hex::encode(bytes)))
+        }
+        Token::Int(int) => Value::Number(Number::from_str(&I256::from_raw(*int).to_string())?),
+        Token::Uint(uint) => Value::Number(Number::from_str(&uint.to_string())?),
+        Token::Bool(bool) => Value::Bool(*bool),
+        Token::String(string) => Value::String(string.clone()),
+        Token::FixedArray(tokens) | Token::Array(tokens) | Token::Tuple(tokens) => Value::Array(
+            tokens
+                .iter()
+                .map(token_to_value)
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers_core::abi::{encode, AbiEncode};
+    use ethers_core::types::U256;
+    use serde_json::{from_value, json};
+
+    use super::*;
+
+    fn calldata(signature: &str, tokens: &[Token]) -> String {
+        let function = HumanReadableParser::parse_function(signature).unwrap();
+        format!(
+            "0x{}{}",
+            hex::encode(function.short_signature()),
+            hex::encode(encode(tokens))
+        )
+    }
+
+    #[test]
+    fn decodes_int_and_array_tokens() {
+        let input = calldata(
+            "function f(int256 delta, uint256[] amounts)",
+            &[
+                Token::Int(I256::from(-5).into_raw()),
+                Token::Array(vec![Token::Uint(U256::from(1)), Token::Uint(U256::from(2))]),
+            ],
+        );
+        let decoded =
+            DecodedInput::from_signature("function f(int256 delta, uint256[] amounts)", &input)
+                .unwrap();
+
+        assert_eq!(decoded.signature, "f(int256,uint256[])");
+        assert_eq!(decoded.params[0].name.as_deref(), Some("delta"));
+        assert_eq!(decoded.params[0].kind, "int256");
+        assert_eq!(decoded.params[0].value, json!(-5));
+        assert_eq!(decoded.params[1].kind, "uint256[]");
+        assert_eq!(decoded.params[1].value, json!([1, 2]));
+    }
+
+    #[test]
+    fn decodes_with_the_abi_function_of_the_selector() {
+        let abi = from_value::<Abi>(json!([
+            {
+                "type": "function",
+                "name": "other",
+                "inputs": [],
+                "outputs": [],
+                "stateMutability": "nonpayable"
+            },
+            {
+                "type": "function",
+                "name": "verify",
+                "inputs": [
+                    {"name": "proof", "type": "uint256[2]"},
+                    {"name": "signal", "type": "uint256"}
+                ],
+                "outputs": [{"name": "", "type": "bool"}],
+                "stateMutability": "view"
+            }
+        ]))
+        .unwrap();
+        let input = calldata(
+            "function verify(uint256[2], uint256)",
+            &[
+                Token::FixedArray(vec![Token::Uint(U256::from(7)), Token::Uint(U256::from(8))]),
+                Token::Uint(U256::from(9)),
+            ],
+        );
+        let decoded = DecodedInput::from_abi(&abi, &input).unwrap();
+
+        assert_eq!(decoded.signature, "verify(uint256[2],uint256)");
+        assert_eq!(decoded.params[0].name.as_deref(), Some("proof"));
+        assert_eq!(decoded.params[0].value, json!([7, 8]));
+        assert_eq!(decoded.params[1].name.as_deref(), Some("signal"));
+
+        let unknown = calldata("function g(uint256)", &[Token::Uint(U256::one())]);
+        assert!(DecodedInput::from_abi(&abi, &unknown).is_err());
+    }
+
+    #[test]
+    fn rejects_selector_mismatch() {
+        let input = calldata("function g(uint256)", &[Token::Uint(U256::one())]);
+        assert!(DecodedInput::from_signature("function f(uint256)", &input).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_calldata() {
+        let selector = HumanReadableParser::parse_function("function f(uint256)")
+            .unwrap()
+            .short_signature();
+        // not hex, then a selector without its argument
+        assert!(DecodedInput::from_signature("function f(uint256)", "0xzz").is_err());
+        assert!(DecodedInput::from_signature(
+            "function f(uint256)",
+            &format!("0x{}", hex::encode(selector))
+        )
+        .is_err());
+        assert!(
+            DecodedInput::from_signature("function f(uint256)", &U256::one().encode_hex()).is_err()
+        );
+    }
+}
@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct NativeCurrency {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+/// Metadata of an indexed chain, as listed in the chain registry
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ChainInfo {
+    pub chain_id: i64,
+    pub name: String,
+    pub native_currency: NativeCurrency,
+    /// Base URL of an external block explorer for the chain
+    pub explorer_url: Option<String>,
+}
@@ -0,0 +1,123 @@
+use anyhow::{anyhow, Error};
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::{header::ACCEPT, request::Parts, HeaderMap, Uri},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{to_value, Value};
+use utoipa::{IntoParams, ToSchema};
+
+use super::validate_block_range;
+use crate::error::AppError;
+
+/// Encoding of a list response, picked from `?format=` or else the `Accept` header
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn negotiate(uri: &Uri, headers: &HeaderMap) -> Result<Self, AppError> {
+        let Query(query) =
+            Query::<FormatQuery>::try_from_uri(uri).map_err(AppError::invalid_parameter)?;
+        if let Some(format) = query.format {
+            return Ok(format);
+        }
+
+        let accept = headers
+            .get(ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .unwrap_or_default();
+        Ok(match accept {
+            accept if accept.contains("text/csv") => ExportFormat::Csv,
+            accept if accept.contains("ndjson") => ExportFormat::Ndjson,
+            _ => ExportFormat::Json,
+        })
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "application/json",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ExportFormat {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Self::negotiate(&parts.uri, &parts.headers)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FormatQuery {
+    /// Overrides the `Accept` header, `json` by default
+    pub format: Option<ExportFormat>,
+}
+
+/// Transactions streamed by `/export/transactions`, all of them when no filter is given
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
+pub struct TransactionExportFilter {
+    /// Only transactions of this chain
+    pub chain_id: Option<i64>,
+    /// Inclusive lower bound on the block number
+    pub from_block: Option<i64>,
+    /// Inclusive upper bound on the block number
+    pub to_block: Option<i64>,
+    /// Only transactions this address sent, received, signed for `ecrecover` or is the closest
+    /// address of
+    pub address: Option<String>,
+    /// Only transactions whose sender, recipient, `ecrecover` signers or closest addresses carry
+    /// this tag
+    pub tag: Option<String>,
+}
+
+impl TransactionExportFilter {
+    pub fn validate(&self) -> Result<(), AppError> {
+        validate_block_range(self.from_block, self.to_block)
+    }
+}
+
+/// Item that can be exported as a CSV row. Nested values are written as JSON.
+pub trait ExportRecord: Serialize {
+    /// CSV header, the serialized field names in column order
+    const HEADERS: &'static [&'static str];
+
+    fn record(&self) -> Result<Vec<String>, Error> {
+        match to_value(self)? {
+            Value::Object(object) => Ok(Self::HEADERS
+                .iter()
+                .map(|header| object.get(*header).map(cell).unwrap_or_default())
+                .collect()),
+            Value::Array(values) => Ok(values.iter().map(cell).collect()),
+            value => Err(anyhow!("Cannot export {} as a CSV row", value)),
+        }
+    }
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
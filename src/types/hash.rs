@@ -0,0 +1,21 @@
+use anyhow::anyhow;
+
+use crate::error::AppError;
+
+/// Lowercases a 32-byte hash and adds the `0x` prefix when missing
+pub fn normalize_hash(hash: &str) -> Result<String, AppError> {
+    let hash = hash.trim().to_lowercase();
+    let hex = hash.strip_prefix("0x").unwrap_or(&hash);
+    if hex.len() != 64 {
+        return Err(AppError::invalid_hash(anyhow!(
+            "Expected 32 bytes (64 hex characters), got {} characters",
+            hex.len()
+        )));
+    }
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(AppError::invalid_hash(anyhow!(
+            "Hash must only contain hex characters"
+        )));
+    }
+    Ok(format!("0x{}", hex))
+}
@@ -0,0 +1,243 @@
+use std::collections::{BTreeSet, HashSet};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use super::{BlockSummary, TransactionSummary};
+use crate::error::AppError;
+
+/// Item of a live feed, identified by its row id
+pub trait FeedItem {
+    fn feed_id(&self) -> i64;
+}
+
+impl FeedItem for BlockSummary {
+    fn feed_id(&self) -> i64 {
+        self.id
+    }
+}
+
+impl FeedItem for TransactionSummary {
+    fn feed_id(&self) -> i64 {
+        self.id
+    }
+}
+
+/// Ids below the highest one delivered that are scanned again. Concurrent indexer writers
+/// commit serial ids out of order, so a row may only show up after higher ids were sent.
+pub const TRAILING_IDS: i64 = 100;
+
+/// Feed items a subscriber was already sent, so that rescanning the trailing ids delivers
+/// late rows once without repeating the others
+#[derive(Debug, Clone, Default)]
+pub struct DeliveredIds {
+    /// Every id up to this one counts as delivered
+    floor: Option<i64>,
+    /// Delivered ids above `floor`
+    recent: BTreeSet<i64>,
+}
+
+impl DeliveredIds {
+    /// Starts with every id up to `last_id` delivered, as when resuming from `Last-Event-ID`
+    pub fn new(last_id: Option<i64>) -> Self {
+        Self {
+            floor: last_id,
+            recent: BTreeSet::new(),
+        }
+    }
+
+    /// Id to replay the feed from, the ids up to it were delivered or are too old to show up
+    pub fn rescan_from(&self) -> Option<i64> {
+        self.floor
+    }
+
+    /// Keeps the replayed items not delivered yet, now counted as delivered
+    pub fn retain_new<T: FeedItem>(&mut self, items: Vec<T>) -> Vec<T> {
+        self.retain(items, true)
+    }
+
+    /// Keeps the broadcast items a replay did not deliver already. The updater only broadcasts
+    /// new items, which unlike replayed ones are kept below the floor too, as a block holding
+    /// its first indexed transaction long after it was stored.
+    pub fn retain_broadcast<T: FeedItem>(&mut self, items: Vec<T>) -> Vec<T> {
+        self.retain(items, false)
+    }
+
+    fn retain<T: FeedItem>(&mut self, items: Vec<T>, replayed: bool) -> Vec<T> {
+        let items = items
+            .into_iter()
+            .filter(|item| {
+                let id = item.feed_id();
+                match self.floor {
+                    Some(floor) if id <= floor => !replayed,
+                    _ => self.recent.insert(id),
+                }
+            })
+            .collect();
+        // ids too far behind the highest one to still be committed are forgotten
+        if let Some(floor) = self.recent.last().map(|id| id.saturating_sub(TRAILING_IDS)) {
+            if self.floor.is_none_or(|current| floor > current) {
+                self.floor = Some(floor);
+                self.recent = self.recent.split_off(&(floor + 1));
+            }
+        }
+        items
+    }
+}
+
+/// What each server-sent event of a live feed carries
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedMode {
+    /// The whole latest window, whenever it changes
+    #[default]
+    Snapshot,
+    /// Only the items added since the previous event
+    Delta,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FeedQuery {
+    pub mode: Option<FeedMode>,
+}
+
+/// Which items of the live feeds a subscriber receives
+#[derive(Debug, Clone, Default)]
+pub struct LiveFilter {
+    pub chain_id: Option<i64>,
+    /// Checksummed addresses a transaction must involve in any role, when given
+    pub addresses: Option<HashSet<String>>,
+}
+
+impl LiveFilter {
+    pub fn matches_block(&self, block: &BlockSummary) -> bool {
+        self.chain_id
+            .is_none_or(|chain_id| block.chain_id == chain_id)
+    }
+
+    pub fn matches_tx(&self, tx: &TransactionSummary) -> bool {
+        if self
+            .chain_id
+            .is_some_and(|chain_id| tx.chain_id != chain_id)
+        {
+            return false;
+        }
+        match &self.addresses {
+            Some(addresses) => [&tx.from_address, &tx.to_address]
+                .into_iter()
+                .chain(&tx.ec_recover_addresses)
+                .chain(&tx.closest_address)
+                .any(|address| addresses.contains(address)),
+            None => true,
+        }
+    }
+}
+
+/// Narrows the transaction feed to a chain, an address or the addresses carrying a tag
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TxFeedFilter {
+    pub chain_id: Option<i64>,
+    /// Only transactions involving this address in any role
+    pub address: Option<String>,
+    /// Only transactions involving an address carrying this tag
+    pub tag: Option<String>,
+}
+
+/// Slice of a latest window served over REST
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LatestQuery {
+    /// Number of items, at most the configured window size
+    pub limit: Option<i64>,
+    /// Only items of this chain, within the window
+    pub chain_id: Option<i64>,
+}
+
+impl LatestQuery {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.limit.is_some_and(|limit| limit < 1) {
+            return Err(AppError::invalid_parameter(anyhow!(
+                "limit must be at least 1"
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn limit(&self, window: i64) -> usize {
+        self.limit.unwrap_or(window).clamp(0, window.max(0)) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Item(i64);
+
+    impl FeedItem for Item {
+        fn feed_id(&self) -> i64 {
+            self.0
+        }
+    }
+
+    fn items(ids: &[i64]) -> Vec<Item> {
+        ids.iter().copied().map(Item).collect()
+    }
+
+    #[test]
+    fn items_are_delivered_once() {
+        let mut delivered = DeliveredIds::default();
+        assert_eq!(delivered.retain_new(items(&[1, 2, 3])), items(&[1, 2, 3]));
+        assert_eq!(delivered.retain_new(items(&[2, 3, 4])), items(&[4]));
+    }
+
+    #[test]
+    fn late_commits_below_the_highest_id_are_delivered() {
+        let mut delivered = DeliveredIds::default();
+        delivered.retain_new(items(&[500, 502]));
+        assert_eq!(
+            delivered.retain_new(items(&[501, 502, 503])),
+            items(&[501, 503])
+        );
+        // rescanning from the floor finds them again without repeating them
+        assert_eq!(delivered.rescan_from(), Some(503 - TRAILING_IDS));
+        assert_eq!(
+            delivered.retain_new(items(&[450, 501, 504])),
+            items(&[450, 504])
+        );
+    }
+
+    #[test]
+    fn resumed_feeds_skip_what_the_client_had() {
+        let mut delivered = DeliveredIds::new(Some(10));
+        assert_eq!(delivered.rescan_from(), Some(10));
+        assert_eq!(delivered.retain_new(items(&[9, 10, 11])), items(&[11]));
+    }
+
+    #[test]
+    fn broadcasts_are_kept_below_the_floor() {
+        let mut delivered = DeliveredIds::new(Some(1000));
+        assert_eq!(
+            delivered.retain_broadcast(items(&[3, 1001])),
+            items(&[3, 1001])
+        );
+        assert_eq!(delivered.retain_new(items(&[1001, 1002])), items(&[1002]));
+        assert_eq!(
+            delivered.retain_broadcast(items(&[1002, 1003])),
+            items(&[1003])
+        );
+    }
+
+    #[test]
+    fn ids_behind_the_trailing_window_are_forgotten() {
+        let mut delivered = DeliveredIds::default();
+        delivered.retain_new(items(&[1, 1000]));
+        assert_eq!(delivered.rescan_from(), Some(1000 - TRAILING_IDS));
+        assert_eq!(delivered.retain_new(items(&[1, 899, 901])), items(&[901]));
+        assert!(delivered.recent.len() <= TRAILING_IDS as usize);
+    }
+}
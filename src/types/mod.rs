@@ -1,2 +1,30 @@
+mod address;
+mod block;
+mod calldata;
+mod chain;
+mod export;
+mod hash;
+mod live;
 mod pagination;
+mod proxy;
+mod response;
+mod search;
+mod stats;
+mod subscription;
+mod tag;
+mod transaction;
+pub use address::*;
+pub use block::*;
+pub use calldata::*;
+pub use chain::*;
+pub use export::*;
+pub use hash::*;
+pub use live::*;
 pub use pagination::*;
+pub use proxy::*;
+pub use response::*;
+pub use search::*;
+pub use stats::*;
+pub use subscription::*;
+pub use tag::*;
+pub use transaction::*;
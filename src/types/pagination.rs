@@ -1,17 +1,258 @@
+use anyhow::anyhow;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
-#[derive(Serialize, Deserialize)]
+use crate::{error::AppError, middleware::CacheVary};
+
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
 pub struct Pagination {
+    /// Number of items per page, at most 100
     pub size: Option<i64>,
+    /// Zero-based page index, at most 10. Ignored when `cursor` is set
     pub page: Option<i64>,
+    /// Opaque `next_cursor` or `prev_cursor` of a previous response
+    pub cursor: Option<String>,
+}
+
+impl CacheVary for Pagination {
+    fn varies_on(param: &str) -> bool {
+        matches!(param, "size" | "page" | "cursor")
+    }
 }
 
 impl Pagination {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.page.is_some_and(|page| page < 0) {
+            return Err(AppError::pagination_out_of_range(anyhow!(
+                "page must not be negative"
+            )));
+        }
+        if self.size.is_some_and(|size| size < 1) {
+            return Err(AppError::pagination_out_of_range(anyhow!(
+                "size must be at least 1"
+            )));
+        }
+        Ok(())
+    }
+
     pub fn offset(&self) -> i64 {
-        self.page.unwrap_or(0).min(10) * self.limit()
+        match self.cursor {
+            Some(_) => 0,
+            None => self.page.unwrap_or(0).min(10) * self.limit(),
+        }
     }
 
     pub fn limit(&self) -> i64 {
         self.size.unwrap_or(50).min(100)
     }
+
+    pub fn cursor(&self) -> Result<Option<Cursor>, AppError> {
+        self.cursor.as_deref().map(Cursor::decode).transpose()
+    }
+
+    /// Cursors around a page whose rows have the given ids, newest first
+    pub fn cursors(&self, ids: &[i64]) -> Result<Cursors, AppError> {
+        let cursor = self.cursor()?;
+        let is_full = ids.len() as i64 >= self.limit();
+        let (Some(first), Some(last)) = (ids.first(), ids.last()) else {
+            // an empty page after a cursor still allows going back to where it came from
+            return Ok(Cursors {
+                next_cursor: cursor
+                    .filter(|cursor| cursor.direction == CursorDirection::Prev)
+                    .map(|cursor| Cursor::next(cursor.id + 1).encode()),
+                prev_cursor: cursor
+                    .filter(|cursor| cursor.direction == CursorDirection::Next)
+                    .map(|cursor| Cursor::prev(cursor.id - 1).encode()),
+            });
+        };
+        let has_next = match cursor {
+            Some(Cursor {
+                direction: CursorDirection::Prev,
+                ..
+            }) => true,
+            _ => is_full,
+        };
+        let has_prev = match cursor {
+            Some(Cursor {
+                direction: CursorDirection::Prev,
+                ..
+            }) => is_full,
+            Some(_) => true,
+            None => self.offset() > 0,
+        };
+        Ok(Cursors {
+            next_cursor: has_next.then(|| Cursor::next(*last).encode()),
+            prev_cursor: has_prev.then(|| Cursor::prev(*first).encode()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorDirection {
+    /// Older rows, with a lower id than the cursor
+    Next,
+    /// Newer rows, with a higher id than the cursor
+    Prev,
+}
+
+/// Position in a list ordered by descending row id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub direction: CursorDirection,
+    pub id: i64,
+}
+
+impl Cursor {
+    pub fn next(id: i64) -> Self {
+        Self {
+            direction: CursorDirection::Next,
+            id,
+        }
+    }
+
+    pub fn prev(id: i64) -> Self {
+        Self {
+            direction: CursorDirection::Prev,
+            id,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let direction = match self.direction {
+            CursorDirection::Next => 'n',
+            CursorDirection::Prev => 'p',
+        };
+        URL_SAFE_NO_PAD.encode(format!("{}:{}", direction, self.id))
+    }
+
+    pub fn decode(token: &str) -> Result<Self, AppError> {
+        let invalid = || AppError::pagination_out_of_range(anyhow!("Invalid cursor"));
+        let decoded = URL_SAFE_NO_PAD.decode(token).map_err(|_| invalid())?;
+        let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+        let (direction, id) = decoded.split_once(':').ok_or_else(invalid)?;
+        let id = id.parse::<i64>().map_err(|_| invalid())?;
+        match direction {
+            "n" => Ok(Self::next(id)),
+            "p" => Ok(Self::prev(id)),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Exclusive upper bound on the row id
+    pub fn before(cursor: Option<Self>) -> Option<i64> {
+        cursor
+            .filter(|cursor| cursor.direction == CursorDirection::Next)
+            .map(|cursor| cursor.id)
+    }
+
+    /// Exclusive lower bound on the row id
+    pub fn after(cursor: Option<Self>) -> Option<i64> {
+        cursor
+            .filter(|cursor| cursor.direction == CursorDirection::Prev)
+            .map(|cursor| cursor.id)
+    }
+
+    /// Sort order to query rows in, `Prev` pages are fetched oldest first and reversed afterwards
+    pub fn order(cursor: Option<Self>) -> &'static str {
+        match cursor {
+            Some(Cursor {
+                direction: CursorDirection::Prev,
+                ..
+            }) => "ASC",
+            _ => "DESC",
+        }
+    }
+}
+
+/// Cursors to the neighbouring pages of a keyset-paginated list
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct Cursors {
+    pub next_cursor: Option<String>,
+    pub prev_cursor: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pagination(size: i64, page: Option<i64>, cursor: Option<Cursor>) -> Pagination {
+        Pagination {
+            size: Some(size),
+            page,
+            cursor: cursor.map(|cursor| cursor.encode()),
+        }
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        for cursor in [Cursor::next(42), Cursor::prev(0), Cursor::next(i64::MAX)] {
+            assert_eq!(Cursor::decode(&cursor.encode()).unwrap(), cursor);
+        }
+    }
+
+    #[test]
+    fn cursor_rejects_garbage() {
+        for token in [
+            "",
+            "not base64!",
+            &URL_SAFE_NO_PAD.encode("x:1"),
+            &URL_SAFE_NO_PAD.encode("n:abc"),
+        ] {
+            assert!(
+                Cursor::decode(token).is_err(),
+                "{:?} should be rejected",
+                token
+            );
+        }
+    }
+
+    #[test]
+    fn cursor_bounds_follow_direction() {
+        assert_eq!(Cursor::before(Some(Cursor::next(10))), Some(10));
+        assert_eq!(Cursor::after(Some(Cursor::next(10))), None);
+        assert_eq!(Cursor::before(Some(Cursor::prev(10))), None);
+        assert_eq!(Cursor::after(Some(Cursor::prev(10))), Some(10));
+        assert_eq!(Cursor::before(None), None);
+        assert_eq!(Cursor::order(Some(Cursor::prev(10))), "ASC");
+        assert_eq!(Cursor::order(Some(Cursor::next(10))), "DESC");
+    }
+
+    #[test]
+    fn first_full_page_only_has_next() {
+        let cursors = pagination(2, None, None).cursors(&[9, 8]).unwrap();
+        assert_eq!(cursors.next_cursor, Some(Cursor::next(8).encode()));
+        assert_eq!(cursors.prev_cursor, None);
+    }
+
+    #[test]
+    fn short_page_has_no_next() {
+        let cursors = pagination(3, None, None).cursors(&[9, 8]).unwrap();
+        assert_eq!(cursors.next_cursor, None);
+    }
+
+    #[test]
+    fn later_offset_page_has_prev() {
+        let cursors = pagination(2, Some(1), None).cursors(&[7, 6]).unwrap();
+        assert_eq!(cursors.prev_cursor, Some(Cursor::prev(7).encode()));
+    }
+
+    #[test]
+    fn prev_page_always_has_next() {
+        let cursors = pagination(2, None, Some(Cursor::prev(7)))
+            .cursors(&[9])
+            .unwrap();
+        assert_eq!(cursors.next_cursor, Some(Cursor::next(9).encode()));
+        assert_eq!(cursors.prev_cursor, None);
+    }
+
+    #[test]
+    fn empty_page_points_back() {
+        let cursors = pagination(2, None, Some(Cursor::next(5)))
+            .cursors(&[])
+            .unwrap();
+        assert_eq!(cursors.next_cursor, None);
+        assert_eq!(cursors.prev_cursor, Some(Cursor::prev(4).encode()));
+    }
 }
@@ -0,0 +1,22 @@
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+use utoipa::ToSchema;
+
+/// The logic contract a proxy delegates to on a given chain
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ProxyDestination {
+    pub logic: String,
+    pub chain_id: i64,
+}
+
+impl TryFrom<&Row> for ProxyDestination {
+    type Error = Error;
+
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        Ok(Self {
+            logic: row.try_get("logic")?,
+            chain_id: row.try_get("chain_id")?,
+        })
+    }
+}
@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{
+    AddressFilter, AddressSummary, AddressTransaction, Block, BlockSummary, ChainInfo, ChainTags,
+    Cursors, Pagination, ProxyDestination, TagCount, TagEntry, Transaction, TransactionSummary,
+    TxCount,
+};
+
+/// Envelope every endpoint wraps its payload in
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[aliases(
+    TransactionResponse = DataResponse<Transaction>,
+    TransactionMatchesResponse = DataResponse<Vec<Transaction>>,
+    AddressSummaryResponse = DataResponse<AddressSummary>,
+    TransactionListResponse = DataResponse<Vec<TransactionSummary>>,
+    BlockResponse = DataResponse<Block>,
+    BlockListResponse = DataResponse<Vec<BlockSummary>>,
+    ChainResponse = DataResponse<ChainInfo>,
+    ChainListResponse = DataResponse<Vec<ChainInfo>>,
+    ProxyDestinationListResponse = DataResponse<Vec<ProxyDestination>>,
+    TagEntryListResponse = DataResponse<Vec<TagEntry>>,
+    TagCountListResponse = DataResponse<Vec<TagCount>>,
+    ChainTagsListResponse = DataResponse<Vec<ChainTags>>,
+    TxCountListResponse = DataResponse<Vec<TxCount>>,
+)]
+pub struct DataResponse<T> {
+    pub data: T,
+}
+
+impl<T> From<T> for DataResponse<T> {
+    fn from(data: T) -> Self {
+        Self { data }
+    }
+}
+
+/// Paginated transaction history of an address
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AddressTransactionsResponse {
+    pub address: String,
+    pub pagination: Pagination,
+    pub filter: AddressFilter,
+    #[serde(flatten)]
+    pub cursors: Cursors,
+    pub data: Vec<AddressTransaction>,
+}
+
+/// Page of addresses carrying a tag
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TagEntryPageResponse {
+    #[serde(flatten)]
+    pub cursors: Cursors,
+    pub data: Vec<TagEntry>,
+}
@@ -0,0 +1,172 @@
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+use utoipa::ToSchema;
+
+use super::{
+    checksum_address, normalize_hash, AddressSummary, Block, TagCount, TransactionSummary,
+};
+use crate::error::AppError;
+
+/// What a search input was classified as
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchQuery {
+    Hash(String),
+    Address(String),
+    Block { chain_id: Option<i64>, number: i64 },
+    Text(String),
+}
+
+impl SearchQuery {
+    pub fn parse(query: &str) -> Result<Self, AppError> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Err(AppError::invalid_parameter(anyhow!("q must not be empty")));
+        }
+
+        let prefixed = query
+            .strip_prefix("0x")
+            .or_else(|| query.strip_prefix("0X"));
+        let hex = prefixed.unwrap_or(query);
+        match (hex.chars().all(|c| c.is_ascii_hexdigit()), hex.len()) {
+            // Like `/tx/:hash`, a hash is accepted without its prefix
+            (true, 64) => return Ok(Self::Hash(normalize_hash(query)?)),
+            (true, 40) if prefixed.is_some() => {
+                return Ok(Self::Address(checksum_address(query)?));
+            }
+            _ => {}
+        }
+
+        if let Ok(number) = query.parse::<i64>() {
+            return Ok(Self::Block {
+                chain_id: None,
+                number,
+            });
+        }
+        if let Some((chain_id, number)) = query.split_once(':') {
+            if let (Ok(chain_id), Ok(number)) = (chain_id.parse::<i64>(), number.parse::<i64>()) {
+                return Ok(Self::Block {
+                    chain_id: Some(chain_id),
+                    number,
+                });
+            }
+        }
+
+        Ok(Self::Text(query.to_string()))
+    }
+
+    pub fn kind(&self) -> SearchKind {
+        match self {
+            SearchQuery::Hash(_) => SearchKind::Hash,
+            SearchQuery::Address(_) => SearchKind::Address,
+            SearchQuery::Block { .. } => SearchKind::Block,
+            SearchQuery::Text(_) => SearchKind::Text,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchKind {
+    Hash,
+    Address,
+    Block,
+    Text,
+}
+
+/// A function signature known to `sig_names`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FunctionMatch {
+    pub signature: String,
+    pub name: String,
+}
+
+impl TryFrom<&Row> for FunctionMatch {
+    type Error = Error;
+
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        Ok(Self {
+            signature: row.try_get("sig")?,
+            name: row.try_get("name")?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SearchResult {
+    Transaction(TransactionSummary),
+    Address(AddressSummary),
+    Block(Block),
+    Tag(TagCount),
+    Function(FunctionMatch),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SearchResponse {
+    pub query: String,
+    pub kind: SearchKind,
+    pub data: Vec<SearchResult>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "0xb5c8bd9430b6cc87a0e2fe110ece6bf527fa4f170a4bc8cd032f768fc5219838";
+
+    #[test]
+    fn hashes_parse_with_or_without_prefix() {
+        for query in [
+            HASH,
+            &HASH[2..],
+            &HASH.to_uppercase().replacen("0X", "0x", 1),
+        ] {
+            assert_eq!(
+                SearchQuery::parse(query).unwrap(),
+                SearchQuery::Hash(HASH.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn addresses_are_checksummed() {
+        assert_eq!(
+            SearchQuery::parse("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").unwrap(),
+            SearchQuery::Address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed".to_string())
+        );
+    }
+
+    #[test]
+    fn numbers_are_blocks() {
+        assert_eq!(
+            SearchQuery::parse("12").unwrap(),
+            SearchQuery::Block {
+                chain_id: None,
+                number: 12
+            }
+        );
+        assert_eq!(
+            SearchQuery::parse("324:12").unwrap(),
+            SearchQuery::Block {
+                chain_id: Some(324),
+                number: 12
+            }
+        );
+    }
+
+    #[test]
+    fn anything_else_is_text() {
+        for query in [
+            "transfer",
+            "5aaeb6053f3e94c9b9a09f33669435e7ef1beaed",
+            "0xabc",
+        ] {
+            assert_eq!(
+                SearchQuery::parse(query).unwrap(),
+                SearchQuery::Text(query.to_string())
+            );
+        }
+        assert!(SearchQuery::parse("  ").is_err());
+    }
+}
@@ -0,0 +1,628 @@
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Serialize};
+use serde_json::{from_value, Value};
+use serde_tuple::{Deserialize_tuple, Serialize_tuple};
+use tokio_postgres::Row;
+use utoipa::{
+    openapi::{ArrayBuilder, ObjectBuilder, RefOr, Schema, SchemaType},
+    IntoParams, ToSchema,
+};
+
+use super::ExportRecord;
+use crate::{error::AppError, middleware::CacheVary};
+
+/// Transaction count of a chain over one interval, serialized as
+/// `[date, chain_id, transaction_count, total_transaction_count]`
+#[derive(Debug, Clone, Serialize_tuple, Deserialize_tuple)]
+pub struct TxCount {
+    pub date: u64,
+    pub chain_id: i64,
+    pub transaction_count: i64,
+    pub total_transaction_count: i64,
+}
+
+impl TryFrom<&Row> for TxCount {
+    type Error = Error;
+
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        Ok(Self {
+            date: row
+                .try_get::<_, SystemTime>("date")?
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_secs(),
+            chain_id: row.try_get("chain_id")?,
+            transaction_count: row.try_get("transaction_count")?,
+            total_transaction_count: row.try_get("total_transaction_count")?,
+        })
+    }
+}
+
+impl ExportRecord for TxCount {
+    const HEADERS: &'static [&'static str] = &[
+        "date",
+        "chain_id",
+        "transaction_count",
+        "total_transaction_count",
+    ];
+}
+
+impl<'s> ToSchema<'s> for TxCount {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        (
+            "TxCount",
+            ArrayBuilder::new()
+                .items(ObjectBuilder::new().schema_type(SchemaType::Integer))
+                .min_items(Some(4))
+                .max_items(Some(4))
+                .description(Some(
+                    "`[date, chain_id, transaction_count, total_transaction_count]`",
+                ))
+                .into(),
+        )
+    }
+}
+
+/// Most buckets a single time series may span
+const MAX_BUCKETS: i64 = 2000;
+
+/// Aggregate computed for every bucket of a time series
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    TxCount,
+    FailedTxCount,
+    UniqueSenders,
+    EcPairingCount,
+    EcRecoverCount,
+    GasUsedTotal,
+    AvgGasPerTx,
+}
+
+impl Metric {
+    /// Aggregate over `transactions` rows
+    pub fn expression(&self) -> &'static str {
+        match self {
+            Metric::TxCount => "COUNT(*)",
+            Metric::FailedTxCount => "COUNT(*) FILTER (WHERE transactions.error IS NOT NULL)",
+            Metric::UniqueSenders => "COUNT(DISTINCT transactions.from_address)",
+            Metric::EcPairingCount => "SUM(transactions.ec_pairing_count)",
+            Metric::EcRecoverCount => "SUM(transactions.ec_recover_count)",
+            Metric::GasUsedTotal => "SUM(transactions.gas_used_total)",
+            Metric::AvgGasPerTx => "AVG(transactions.gas_used_total)",
+        }
+    }
+}
+
+/// Width of a time series bucket
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsInterval {
+    Hour,
+    #[default]
+    Day,
+    Week,
+}
+
+impl StatsInterval {
+    /// Field name understood by `date_trunc`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StatsInterval::Hour => "hour",
+            StatsInterval::Day => "day",
+            StatsInterval::Week => "week",
+        }
+    }
+
+    pub fn seconds(&self) -> i64 {
+        match self {
+            StatsInterval::Hour => 3600,
+            StatsInterval::Day => 86400,
+            StatsInterval::Week => 7 * 86400,
+        }
+    }
+
+    /// Seconds `timestamp` is past the start of its bucket
+    pub fn offset(&self, timestamp: i64) -> i64 {
+        // 1970-01-01 was a Thursday, weeks start 4 days later
+        let monday = match self {
+            StatsInterval::Week => 4 * 86400,
+            _ => 0,
+        };
+        (timestamp.rem_euclid(self.seconds()) + self.seconds() - monday) % self.seconds()
+    }
+
+    /// Range covered when `from` is not given
+    pub fn default_span(&self) -> i64 {
+        match self {
+            StatsInterval::Hour => 2 * 86400,
+            StatsInterval::Day => 90 * 86400,
+            StatsInterval::Week => 365 * 86400,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TimeseriesQuery {
+    pub metric: Metric,
+    pub chain_id: Option<i64>,
+    /// Bucket width, `day` by default
+    pub interval: Option<StatsInterval>,
+    /// Start of the range as a unix timestamp, inclusive
+    pub from: Option<i64>,
+    /// End of the range as a unix timestamp, exclusive. Now by default
+    pub to: Option<i64>,
+}
+
+impl CacheVary for TimeseriesQuery {
+    fn varies_on(param: &str) -> bool {
+        matches!(param, "metric" | "chain_id" | "interval" | "from" | "to")
+    }
+}
+
+impl TimeseriesQuery {
+    pub fn interval(&self) -> StatsInterval {
+        self.interval.unwrap_or_default()
+    }
+
+    /// `[from, to)` with the defaults applied, relative to `now`, widened to whole buckets
+    ///
+    /// Aligning the range lets the raw and the materialized view queries cover the same
+    /// transactions, the view only knowing the start of each day.
+    pub fn range(&self, now: i64) -> Result<(i64, i64), AppError> {
+        let interval = self.interval();
+        let (from, to) = time_range(self.from, self.to, interval.default_span(), now)?;
+        let from = from.checked_sub(interval.offset(from));
+        let to = match interval.offset(to) {
+            0 => Some(to),
+            offset => to.checked_add(interval.seconds() - offset),
+        };
+        let (from, to) = from.zip(to).ok_or_else(out_of_range)?;
+        match to.checked_sub(from).ok_or_else(out_of_range)? / interval.seconds() {
+            buckets if buckets > MAX_BUCKETS => Err(AppError::invalid_parameter(anyhow!(
+                "Range spans {} buckets, at most {} are allowed",
+                buckets,
+                MAX_BUCKETS
+            ))),
+            _ => Ok((from, to)),
+        }
+    }
+}
+
+/// `[from, to)` defaulting to the `span` seconds before `now`
+pub fn time_range(
+    from: Option<i64>,
+    to: Option<i64>,
+    span: i64,
+    now: i64,
+) -> Result<(i64, i64), AppError> {
+    let to = to.unwrap_or(now);
+    let from = match from {
+        Some(from) => from,
+        None => to.checked_sub(span).ok_or_else(out_of_range)?,
+    };
+    if from >= to {
+        return Err(AppError::invalid_parameter(anyhow!(
+            "from must be before to"
+        )));
+    }
+    Ok((from, to))
+}
+
+fn out_of_range() -> AppError {
+    AppError::invalid_parameter(anyhow!("from or to is out of range"))
+}
+
+/// Value of a metric for one chain over one bucket
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TimeseriesPoint {
+    /// Start of the bucket as a unix timestamp
+    pub date: u64,
+    pub chain_id: i64,
+    pub value: f64,
+}
+
+impl TryFrom<&Row> for TimeseriesPoint {
+    type Error = Error;
+
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        Ok(Self {
+            date: row
+                .try_get::<_, SystemTime>("date")?
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_secs(),
+            chain_id: row.try_get("chain_id")?,
+            value: row.try_get::<_, Option<f64>>("value")?.unwrap_or_default(),
+        })
+    }
+}
+
+/// A metric bucketed over time, one series per chain
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TimeseriesResponse {
+    pub metric: Metric,
+    pub interval: StatsInterval,
+    pub from: i64,
+    pub to: i64,
+    pub data: Vec<TimeseriesPoint>,
+}
+
+/// What a leaderboard ranks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TopKind {
+    /// `to_address` of transactions
+    Contracts,
+    /// `from_address` of transactions
+    Senders,
+    /// `function_signature` of transactions
+    Functions,
+}
+
+impl TopKind {
+    pub fn parse(kind: &str) -> Result<Self, AppError> {
+        match kind {
+            "contracts" => Ok(TopKind::Contracts),
+            "senders" => Ok(TopKind::Senders),
+            "functions" => Ok(TopKind::Functions),
+            _ => Err(AppError::invalid_parameter(anyhow!(
+                "Expected contracts, senders or functions, got {}",
+                kind
+            ))),
+        }
+    }
+
+    /// Column of `transactions` entries are grouped by
+    pub fn column(&self) -> &'static str {
+        match self {
+            TopKind::Contracts => "transactions.to_address",
+            TopKind::Senders => "transactions.from_address",
+            TopKind::Functions => "transactions.function_signature",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TopOrder {
+    #[default]
+    TxCount,
+    GasUsed,
+    EcPairingCount,
+}
+
+impl TopOrder {
+    pub fn column(&self) -> &'static str {
+        match self {
+            TopOrder::TxCount => "tx_count",
+            TopOrder::GasUsed => "gas_used",
+            TopOrder::EcPairingCount => "ec_pairing_count",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TopQuery {
+    pub chain_id: Option<i64>,
+    /// Start of the window as a unix timestamp, 30 days before `to` by default
+    pub from: Option<i64>,
+    /// End of the window as a unix timestamp, exclusive. Now by default
+    pub to: Option<i64>,
+    /// `tx_count` by default
+    pub order_by: Option<TopOrder>,
+    /// Number of entries, 20 by default and at most 100
+    pub limit: Option<i64>,
+}
+
+impl CacheVary for TopQuery {
+    fn varies_on(param: &str) -> bool {
+        matches!(param, "chain_id" | "from" | "to" | "order_by" | "limit")
+    }
+}
+
+impl TopQuery {
+    pub const DEFAULT_SPAN: i64 = 30 * 86400;
+
+    pub fn limit(&self) -> i64 {
+        self.limit.unwrap_or(20).clamp(1, 100)
+    }
+}
+
+/// An address or function signature with its activity over the window
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopEntry {
+    /// Address, or function signature for `functions`
+    pub key: String,
+    /// Name of the function, for `functions`
+    pub name: Option<String>,
+    /// Tags of the address, for `contracts` and `senders`
+    pub tags: Vec<String>,
+    pub tx_count: i64,
+    pub gas_used: i64,
+    pub ec_pairing_count: i64,
+}
+
+impl TryFrom<&Row> for TopEntry {
+    type Error = Error;
+
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        Ok(Self {
+            key: row.try_get("key")?,
+            name: row.try_get("name")?,
+            tags: row.try_get("tags")?,
+            tx_count: row.try_get("tx_count")?,
+            gas_used: row.try_get("gas_used")?,
+            ec_pairing_count: row.try_get("ec_pairing_count")?,
+        })
+    }
+}
+
+/// Leaderboard of the most active contracts, senders or functions over a window
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TopResponse {
+    pub kind: TopKind,
+    pub order_by: TopOrder,
+    pub from: i64,
+    pub to: i64,
+    pub data: Vec<TopEntry>,
+}
+
+/// What gas statistics are grouped by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GasGroup {
+    /// `to_address` of transactions
+    #[default]
+    Contract,
+    /// `function_signature` of transactions
+    Function,
+}
+
+impl GasGroup {
+    pub fn column(&self) -> &'static str {
+        match self {
+            GasGroup::Contract => "transactions.to_address",
+            GasGroup::Function => "transactions.function_signature",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GasQuery {
+    /// `contract` by default
+    pub group_by: Option<GasGroup>,
+    pub chain_id: Option<i64>,
+    /// Only transactions sent to this contract
+    pub address: Option<String>,
+    /// Only transactions calling this function
+    pub function_signature: Option<String>,
+    /// Start of the window as a unix timestamp, 30 days before `to` by default
+    pub from: Option<i64>,
+    /// End of the window as a unix timestamp, exclusive. Now by default
+    pub to: Option<i64>,
+    /// Number of histogram buckets, 20 by default and at most 100
+    pub buckets: Option<i32>,
+    /// Number of groups, the most used first. 20 by default and at most 100
+    pub limit: Option<i64>,
+}
+
+impl CacheVary for GasQuery {
+    fn varies_on(param: &str) -> bool {
+        matches!(
+            param,
+            "group_by"
+                | "chain_id"
+                | "address"
+                | "function_signature"
+                | "from"
+                | "to"
+                | "buckets"
+                | "limit"
+        )
+    }
+}
+
+impl GasQuery {
+    pub const DEFAULT_SPAN: i64 = 30 * 86400;
+
+    pub fn buckets(&self) -> i32 {
+        self.buckets.unwrap_or(20).clamp(1, 100)
+    }
+
+    pub fn limit(&self) -> i64 {
+        self.limit.unwrap_or(20).clamp(1, 100)
+    }
+}
+
+/// Transactions whose `gas_used_total` falls within `[lower, upper)`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HistogramBucket {
+    pub lower: i64,
+    pub upper: i64,
+    pub count: i64,
+}
+
+/// Distribution of `gas_used_total` for a contract or function
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct GasStats {
+    /// Contract address, or function signature for `function`
+    pub key: String,
+    /// Name of the function, for `function`
+    pub name: Option<String>,
+    pub tx_count: i64,
+    pub min: i64,
+    pub median: f64,
+    pub p95: f64,
+    pub max: i64,
+    /// Total gas used divided by the total number of EC pairings, when there were any
+    pub gas_per_pairing: Option<f64>,
+    pub histogram: Vec<HistogramBucket>,
+}
+
+impl TryFrom<&Row> for GasStats {
+    type Error = Error;
+
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        let min: i64 = row.try_get("min")?;
+        let max: i64 = row.try_get("max")?;
+        let buckets: i32 = row.try_get("buckets")?;
+        let counts = match row.try_get::<_, Option<Value>>("histogram")? {
+            Some(histogram) => from_value::<Vec<(i32, i64)>>(histogram)?,
+            None => Vec::new(),
+        };
+
+        let histogram = histogram(min, max, buckets, &counts);
+
+        Ok(Self {
+            key: row.try_get("key")?,
+            name: row.try_get("name")?,
+            tx_count: row.try_get("tx_count")?,
+            min,
+            median: row.try_get("median")?,
+            p95: row.try_get("p95")?,
+            max,
+            gas_per_pairing: row.try_get("gas_per_pairing")?,
+            histogram,
+        })
+    }
+}
+
+/// Buckets of `width_bucket(gas, min, max + 1, buckets)`, numbered from 1, with their counts
+fn histogram(min: i64, max: i64, buckets: i32, counts: &[(i32, i64)]) -> Vec<HistogramBucket> {
+    let width = (max as f64 + 1.0 - min as f64) / buckets as f64;
+    let bound = |bucket: i32| min + (bucket as f64 * width).round() as i64;
+    (1..=buckets)
+        .map(|bucket| HistogramBucket {
+            lower: bound(bucket - 1),
+            upper: bound(bucket),
+            count: counts
+                .iter()
+                .find(|(b, _)| *b == bucket)
+                .map(|(_, count)| *count)
+                .unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Gas cost distributions of the most used contracts or functions over a window
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct GasResponse {
+    pub group_by: GasGroup,
+    pub from: i64,
+    pub to: i64,
+    pub data: Vec<GasStats>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn timeseries(interval: StatsInterval, from: Option<i64>, to: Option<i64>) -> TimeseriesQuery {
+        TimeseriesQuery {
+            metric: Metric::TxCount,
+            chain_id: None,
+            interval: Some(interval),
+            from,
+            to,
+        }
+    }
+
+    #[test]
+    fn time_range_defaults_to_the_span_before_now() {
+        assert_eq!(time_range(None, None, 60, NOW).unwrap(), (NOW - 60, NOW));
+        assert_eq!(time_range(None, Some(100), 60, NOW).unwrap(), (40, 100));
+        assert_eq!(time_range(Some(10), None, 60, NOW).unwrap(), (10, NOW));
+    }
+
+    #[test]
+    fn time_range_rejects_empty_and_overflowing_ranges() {
+        for (from, to) in [
+            (Some(100), Some(100)),
+            (Some(101), Some(100)),
+            (None, Some(i64::MIN)),
+        ] {
+            let err = time_range(from, to, 60, NOW).unwrap_err();
+            assert_eq!(err.code(), ErrorCode::InvalidParameter);
+        }
+    }
+
+    #[test]
+    fn weeks_start_on_monday() {
+        // 2023-11-13 was a Monday
+        let monday = 1_699_833_600;
+        assert_eq!(StatsInterval::Week.offset(monday), 0);
+        assert_eq!(StatsInterval::Week.offset(monday + 86400 + 5), 86400 + 5);
+        assert_eq!(StatsInterval::Week.offset(monday - 1), 7 * 86400 - 1);
+        assert_eq!(StatsInterval::Day.offset(monday + 3600), 3600);
+        assert_eq!(StatsInterval::Hour.offset(-1), 3599);
+    }
+
+    #[test]
+    fn timeseries_range_covers_whole_buckets() {
+        let query = timeseries(StatsInterval::Day, Some(86400 + 5), Some(3 * 86400 + 5));
+        assert_eq!(query.range(NOW).unwrap(), (86400, 4 * 86400));
+        let query = timeseries(StatsInterval::Hour, Some(3600), Some(7200));
+        assert_eq!(query.range(NOW).unwrap(), (3600, 7200));
+        let (from, to) = timeseries(StatsInterval::Week, None, None)
+            .range(NOW)
+            .unwrap();
+        assert_eq!(StatsInterval::Week.offset(from), 0);
+        assert_eq!(StatsInterval::Week.offset(to), 0);
+        assert!(from <= NOW - 365 * 86400 && to > NOW);
+    }
+
+    #[test]
+    fn timeseries_range_rejects_too_many_buckets() {
+        let query = timeseries(StatsInterval::Hour, Some(0), Some(2000 * 3600));
+        assert!(query.range(NOW).is_ok());
+        let query = timeseries(StatsInterval::Hour, Some(0), Some(2001 * 3600));
+        assert_eq!(
+            query.range(NOW).unwrap_err().code(),
+            ErrorCode::InvalidParameter
+        );
+    }
+
+    #[test]
+    fn timeseries_range_rejects_extreme_values() {
+        for (from, to) in [
+            (Some(i64::MIN), Some(i64::MAX)),
+            (Some(i64::MIN), Some(0)),
+            (Some(0), Some(i64::MAX)),
+            (None, Some(i64::MIN + 1)),
+        ] {
+            let err = timeseries(StatsInterval::Day, from, to)
+                .range(NOW)
+                .unwrap_err();
+            assert_eq!(err.code(), ErrorCode::InvalidParameter);
+        }
+    }
+
+    #[test]
+    fn histogram_buckets_split_min_to_max() {
+        let histogram = histogram(100, 199, 4, &[(1, 3), (4, 1)]);
+        let bounds: Vec<_> = histogram
+            .iter()
+            .map(|b| (b.lower, b.upper, b.count))
+            .collect();
+        assert_eq!(
+            bounds,
+            [(100, 125, 3), (125, 150, 0), (150, 175, 0), (175, 200, 1)]
+        );
+    }
+
+    #[test]
+    fn histogram_of_a_single_value_is_one_gas_wide() {
+        let histogram = histogram(7, 7, 1, &[(1, 2)]);
+        assert_eq!(
+            (histogram[0].lower, histogram[0].upper, histogram[0].count),
+            (7, 8, 2)
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::AppError;
+
+/// Feed a websocket subscription listens to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SubscriptionTopic {
+    NewBlocks,
+    NewTxs,
+    /// New transactions involving `address` in any role
+    AddressActivity,
+    /// New transactions involving any address tagged with `tag`
+    TagActivity,
+}
+
+/// Optional second element of the `subscribe` params
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SubscriptionParams {
+    pub chain_id: Option<i64>,
+    pub address: Option<String>,
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RpcRequest {
+    #[serde(default)]
+    pub id: Value,
+    pub method: String,
+    #[serde(default)]
+    pub params: Vec<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i32,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+}
+
+impl RpcError {
+    pub const PARSE_ERROR: i32 = -32700;
+    pub const METHOD_NOT_FOUND: i32 = -32601;
+    pub const INVALID_PARAMS: i32 = -32602;
+    pub const INTERNAL_ERROR: i32 = -32603;
+
+    pub fn new(code: i32, message: &str, data: Option<String>) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+            data,
+        }
+    }
+
+    pub fn parse_error<E: ToString>(err: E) -> Self {
+        Self::new(Self::PARSE_ERROR, "Parse error", Some(err.to_string()))
+    }
+
+    pub fn method_not_found(method: &str) -> Self {
+        Self::new(
+            Self::METHOD_NOT_FOUND,
+            "Method not found",
+            Some(method.to_string()),
+        )
+    }
+
+    pub fn invalid_params<E: ToString>(err: E) -> Self {
+        Self::new(
+            Self::INVALID_PARAMS,
+            "Invalid params",
+            Some(err.to_string()),
+        )
+    }
+}
+
+impl From<AppError> for RpcError {
+    fn from(err: AppError) -> Self {
+        let code = match err.code().is_client_error() {
+            true => Self::INVALID_PARAMS,
+            false => {
+                error!("{:?}", err);
+                Self::INTERNAL_ERROR
+            }
+        };
+        Self::new(code, err.code().message(), err.details())
+    }
+}
+
+/// Reply to a single request, carrying either `result` or `error`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RpcResponse {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl RpcResponse {
+    pub fn new(id: Value, result: Result<Value, RpcError>) -> Self {
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result,
+            error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionResult {
+    pub subscription: u64,
+    pub result: Value,
+}
+
+/// Message pushed to the client for every delta matching a subscription
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionNotification {
+    pub jsonrpc: String,
+    pub method: String,
+    pub params: SubscriptionResult,
+}
+
+impl SubscriptionNotification {
+    pub fn new(subscription: u64, result: Value) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            method: "subscription".to_string(),
+            params: SubscriptionResult {
+                subscription,
+                result,
+            },
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::from_value;
+use tokio_postgres::Row;
+use utoipa::ToSchema;
+
+use super::ExportRecord;
+
+/// An address together with every tag attached to it
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TagEntry {
+    pub address: String,
+    pub tags: Vec<String>,
+}
+
+impl ExportRecord for TagEntry {
+    const HEADERS: &'static [&'static str] = &["address", "tags"];
+}
+
+impl TryFrom<&Row> for TagEntry {
+    type Error = Error;
+
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        Ok(Self {
+            address: row.try_get("address")?,
+            tags: row.try_get("tags")?,
+        })
+    }
+}
+
+/// A tag and how many addresses carry it
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: i64,
+}
+
+impl TryFrom<&Row> for TagCount {
+    type Error = Error;
+
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        Ok(Self {
+            tag: row.try_get("tag")?,
+            count: row.try_get("count")?,
+        })
+    }
+}
+
+/// Tag counts of a single chain
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ChainTags {
+    pub chain_id: i64,
+    pub tags: Vec<TagCount>,
+}
+
+impl TryFrom<&Row> for ChainTags {
+    type Error = Error;
+
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        Ok(Self {
+            chain_id: row.try_get("chain_id")?,
+            tags: from_value(row.try_get("tags")?)?,
+        })
+    }
+}
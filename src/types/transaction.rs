@@ -0,0 +1,145 @@
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str, Number};
+use tokio_postgres::Row;
+
+use super::{DecodedInput, ExportRecord};
+use utoipa::ToSchema;
+
+/// Tables every transaction query selects from, so that `sig_names` and `blocks` columns are
+/// available to [`Transaction::COLUMNS`] and [`TransactionSummary::COLUMNS`]
+pub const TRANSACTION_FROM: &str = "transactions LEFT JOIN sig_names ON transactions.function_signature = sig_names.sig LEFT JOIN blocks ON blocks.chain_id = transactions.chain_id AND blocks.number = transactions.block_number";
+
+/// A single transaction with its full detail, as returned by `/tx/:hash`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Transaction {
+    pub chain_id: i64,
+    pub from_address: String,
+    pub to_address: String,
+    pub transaction_hash: String,
+    pub transaction_index: i32,
+    pub block_number: i64,
+    pub block_timestamp: Option<i64>,
+    #[schema(value_type = u128)]
+    pub value: Number,
+    pub input: String,
+    pub gas_used_total: i64,
+    pub gas_used_first_degree: i64,
+    pub error: Option<String>,
+    pub function_signature: Option<String>,
+    pub function_name: Option<String>,
+    pub ec_pairing_count: i16,
+    pub ec_recover_count: i16,
+    pub ec_recover_addresses: Vec<String>,
+    pub closest_address: Vec<String>,
+    /// `input` decoded with an uploaded ABI or the `function_name` signature, if possible
+    pub decoded_input: Option<DecodedInput>,
+}
+
+impl Transaction {
+    pub const COLUMNS: &'static str = "transactions.chain_id, transactions.from_address, transactions.to_address, transactions.transaction_hash, transactions.transaction_index, transactions.block_number, blocks.timestamp AS block_timestamp, transactions.value, transactions.input, transactions.gas_used_total, transactions.gas_used_first_degree, transactions.error, transactions.function_signature, sig_names.name AS function_name, transactions.ec_pairing_count, transactions.ec_recover_count, transactions.ec_recover_addresses, transactions.closest_address";
+}
+
+impl TryFrom<&Row> for Transaction {
+    type Error = Error;
+
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        Ok(Self {
+            chain_id: row.try_get("chain_id")?,
+            from_address: row.try_get("from_address")?,
+            to_address: row.try_get("to_address")?,
+            transaction_hash: row.try_get("transaction_hash")?,
+            transaction_index: row.try_get("transaction_index")?,
+            block_number: row.try_get("block_number")?,
+            block_timestamp: row.try_get("block_timestamp")?,
+            value: from_str(row.try_get("value")?)?,
+            input: row.try_get("input")?,
+            gas_used_total: row.try_get("gas_used_total")?,
+            gas_used_first_degree: row.try_get("gas_used_first_degree")?,
+            error: row.try_get("error")?,
+            function_signature: row.try_get("function_signature")?,
+            function_name: row.try_get("function_name")?,
+            ec_pairing_count: row.try_get("ec_pairing_count")?,
+            ec_recover_count: row.try_get("ec_recover_count")?,
+            ec_recover_addresses: row.try_get("ec_recover_addresses")?,
+            closest_address: row.try_get("closest_address")?,
+            decoded_input: None,
+        })
+    }
+}
+
+/// A transaction as it appears in lists (address history, block txs, latest feed)
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TransactionSummary {
+    /// Row id, used as the keyset for cursors and live feeds
+    #[serde(skip)]
+    pub id: i64,
+    pub chain_id: i64,
+    pub block_number: i64,
+    pub block_timestamp: Option<i64>,
+    pub transaction_hash: String,
+    pub transaction_index: i32,
+    pub from_address: String,
+    pub to_address: String,
+    #[schema(value_type = u128)]
+    pub value: Number,
+    pub error: Option<String>,
+    pub function_signature: Option<String>,
+    pub function_name: Option<String>,
+    pub ec_pairing_count: i16,
+    pub ec_recover_addresses: Vec<String>,
+    pub closest_address: Vec<String>,
+    pub gas_used_total: i64,
+    pub gas_used_first_degree: i64,
+}
+
+impl TransactionSummary {
+    pub const COLUMNS: &'static str = "transactions.id, transactions.chain_id, transactions.block_number, blocks.timestamp AS block_timestamp, transactions.transaction_hash, transactions.transaction_index, transactions.from_address, transactions.to_address, transactions.value, transactions.error, transactions.function_signature, sig_names.name AS function_name, transactions.ec_pairing_count, transactions.ec_recover_addresses, transactions.closest_address, transactions.gas_used_total, transactions.gas_used_first_degree";
+}
+
+impl TryFrom<&Row> for TransactionSummary {
+    type Error = Error;
+
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            chain_id: row.try_get("chain_id")?,
+            block_number: row.try_get("block_number")?,
+            block_timestamp: row.try_get("block_timestamp")?,
+            transaction_hash: row.try_get("transaction_hash")?,
+            transaction_index: row.try_get("transaction_index")?,
+            from_address: row.try_get("from_address")?,
+            to_address: row.try_get("to_address")?,
+            value: from_str(row.try_get("value")?)?,
+            error: row.try_get("error")?,
+            function_signature: row.try_get("function_signature")?,
+            function_name: row.try_get("function_name")?,
+            ec_pairing_count: row.try_get("ec_pairing_count")?,
+            ec_recover_addresses: row.try_get("ec_recover_addresses")?,
+            closest_address: row.try_get("closest_address")?,
+            gas_used_total: row.try_get("gas_used_total")?,
+            gas_used_first_degree: row.try_get("gas_used_first_degree")?,
+        })
+    }
+}
+
+impl ExportRecord for TransactionSummary {
+    const HEADERS: &'static [&'static str] = &[
+        "chain_id",
+        "block_number",
+        "block_timestamp",
+        "transaction_hash",
+        "transaction_index",
+        "from_address",
+        "to_address",
+        "value",
+        "error",
+        "function_signature",
+        "function_name",
+        "ec_pairing_count",
+        "ec_recover_addresses",
+        "closest_address",
+        "gas_used_total",
+        "gas_used_first_degree",
+    ];
+}
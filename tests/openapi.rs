@@ -0,0 +1,140 @@
+use std::{collections::BTreeSet, env, fs, time::Duration};
+
+use axum::{
+    body::Body,
+    http::{header::CONTENT_TYPE, Request, StatusCode},
+};
+use serde_json::{to_value, Value};
+use tokio::time::timeout;
+use tower::ServiceExt;
+use utoipa::OpenApi;
+use zkscan_api::api::{self, openapi::ApiDoc};
+
+const SNAPSHOT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+/// Routes serving the documentation itself, left out of the spec
+const UNDOCUMENTED_ROUTES: &[&str] = &["/docs", "/openapi.json"];
+
+/// The checked-in `openapi.json` must match the spec generated from the handlers.
+/// Run with `UPDATE_OPENAPI=1` to regenerate it after changing a route.
+#[test]
+fn openapi_spec_matches_snapshot() {
+    let spec = ApiDoc::openapi()
+        .to_pretty_json()
+        .expect("Failed to serialize OpenAPI spec");
+
+    if env::var("UPDATE_OPENAPI").is_ok() {
+        fs::write(SNAPSHOT, format!("{}\n", spec)).expect("Failed to write openapi.json");
+        return;
+    }
+
+    let snapshot = fs::read_to_string(SNAPSHOT).expect("Failed to read openapi.json");
+    assert_eq!(
+        snapshot.trim_end(),
+        spec,
+        "openapi.json is out of date, rerun the tests with UPDATE_OPENAPI=1"
+    );
+}
+
+/// Every `$ref` of the spec must point to a component it defines
+#[test]
+fn openapi_refs_resolve() {
+    let spec = to_value(ApiDoc::openapi()).expect("Failed to serialize OpenAPI spec");
+
+    let mut refs = BTreeSet::new();
+    collect_refs(&spec, &mut refs);
+
+    assert!(!refs.is_empty(), "Failed to find any $ref in the spec");
+    let dangling = refs
+        .iter()
+        .filter(|reference| {
+            reference
+                .strip_prefix('#')
+                .and_then(|pointer| spec.pointer(pointer))
+                .is_none()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(dangling, Vec::<&String>::new(), "unresolved $refs");
+}
+
+fn collect_refs(value: &Value, refs: &mut BTreeSet<String>) {
+    match value {
+        Value::Object(object) => {
+            if let Some(Value::String(reference)) = object.get("$ref") {
+                refs.insert(reference.clone());
+            }
+            object.values().for_each(|value| collect_refs(value, refs));
+        }
+        Value::Array(values) => values.iter().for_each(|value| collect_refs(value, refs)),
+        _ => {}
+    }
+}
+
+/// Every path of [`api::ROUTES`] must be documented under the same path, and every documented
+/// path must be listed there.
+#[test]
+fn openapi_paths_match_routes() {
+    let documented = ApiDoc::openapi()
+        .paths
+        .paths
+        .keys()
+        .map(|path| path.replace('{', ":").replace('}', ""))
+        .collect::<BTreeSet<_>>();
+    let listed = api::ROUTES
+        .iter()
+        .filter(|path| !UNDOCUMENTED_ROUTES.contains(path))
+        .map(|path| path.to_string())
+        .collect::<BTreeSet<_>>();
+
+    assert_eq!(
+        documented.difference(&listed).collect::<Vec<_>>(),
+        Vec::<&String>::new(),
+        "documented paths missing from api::ROUTES"
+    );
+    assert_eq!(
+        listed.difference(&documented).collect::<Vec<_>>(),
+        Vec::<&String>::new(),
+        "api::ROUTES paths missing from ApiDoc"
+    );
+}
+
+/// Every path of [`api::ROUTES`] must reach a handler. Without a database the handlers fail, but
+/// with a JSON error rather than the empty 404 of an unknown path.
+#[tokio::test]
+async fn routes_are_served() {
+    // building the router only creates the pools, requests fail to connect
+    for (name, value) in [
+        ("POSTGRES_HOST", "127.0.0.1"),
+        ("POSTGRES_USERNAME", "zkscan"),
+        ("POSTGRES_PASSWORD", "zkscan"),
+        ("POSTGRES_DB", "zkscan"),
+        ("REDIS_URL", "redis://127.0.0.1:1"),
+    ] {
+        if env::var(name).is_err() {
+            env::set_var(name, value);
+        }
+    }
+
+    let router = api::routes();
+    for path in api::ROUTES {
+        let uri = path
+            .split('/')
+            .map(|segment| match segment.starts_with(':') {
+                true => "1",
+                false => segment,
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        let request = Request::get(&uri).body(Body::empty()).unwrap();
+        let response = timeout(Duration::from_secs(10), router.clone().oneshot(request))
+            .await
+            .unwrap_or_else(|_| panic!("{} timed out", uri))
+            .unwrap();
+        assert!(
+            response.status() != StatusCode::NOT_FOUND
+                || response.headers().contains_key(CONTENT_TYPE),
+            "{} is not routed",
+            path
+        );
+    }
+}